@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use url::Url;
+
+use super::link_preview::{self, LinkPreviewOptions, CLIENT};
+use crate::errors::Result as PResult;
+
+/// The maximum size of the article page that is read.
+const MAX_ARTICLE_SIZE: usize = 5 * 1024 * 1024;
+/// The minimum length of a paragraph, the shorter ones are usually captions and buttons.
+const MIN_PARAGRAPH_LENGTH: usize = 25;
+/// The maximum part of a paragraph text that is in links, the others are usually menus.
+const MAX_LINK_DENSITY: f32 = 0.5;
+/// The elements that are not a part of the article content.
+const BOILERPLATE_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "nav", "header", "footer", "aside", "form",
+    "figure",
+];
+
+/// Matches the HTML comments.
+static COMMENT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<!--.*?-->").unwrap());
+/// Matches the boilerplate elements, see [`BOILERPLATE_TAGS`].
+static BOILERPLATE_RES: Lazy<Vec<Regex>> = Lazy::new(|| {
+    BOILERPLATE_TAGS
+        .iter()
+        .map(|tag| Regex::new(&format!(r"(?is)<{tag}\b.*?</{tag}\s*>")).unwrap())
+        .collect()
+});
+/// Matches the elements that contain the article, in the order they are tried.
+static CONTAINER_RES: Lazy<Vec<Regex>> = Lazy::new(|| {
+    ["article", "main", "body"]
+        .iter()
+        .map(|tag| Regex::new(&format!(r"(?is)<{tag}\b[^>]*>(.*)</{tag}\s*>")).unwrap())
+        .collect()
+});
+/// Matches the paragraphs.
+static PARAGRAPH_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<p\b[^>]*>(.*?)</p\s*>").unwrap());
+/// Matches the links text.
+static LINK_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<a\b[^>]*>(.*?)</a\s*>").unwrap());
+/// Matches the HTML tags.
+static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+/// Returns the main content of the article, at most `max_size` bytes.
+/// The article is downloaded with the link previews timeout and rate limit.
+/// Returns `None` if the article has no content paragraphs.
+pub async fn fetch(
+    link: &str,
+    max_size: usize,
+    options: &LinkPreviewOptions,
+) -> PResult<Option<String>> {
+    if let Ok(url) = Url::parse(link) {
+        link_preview::wait_turn(&url, Duration::from_secs(options.interval)).await;
+    }
+    tracing::info!("Fetching the full text of: {}", link);
+    let mut res = CLIENT
+        .get(link)
+        .timeout(Duration::from_secs(options.timeout))
+        .send()
+        .await?
+        .error_for_status()?;
+    let mut page = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        page.extend_from_slice(&chunk);
+        if page.len() > MAX_ARTICLE_SIZE {
+            break;
+        }
+    }
+    Ok(extract(&String::from_utf8_lossy(&page)).map(|text| truncate(&text, max_size)))
+}
+
+/// Extracts the main content of the page, the paragraphs of its article.
+/// The boilerplate elements are removed, then the paragraphs of the first `<article>`,
+/// `<main>` or `<body>` that has content are kept, without the short and the link ones.
+fn extract(page: &str) -> Option<String> {
+    let mut page = COMMENT_RE.replace_all(page, "").into_owned();
+    for boilerplate in BOILERPLATE_RES.iter() {
+        page = boilerplate.replace_all(&page, "").into_owned();
+    }
+    CONTAINER_RES
+        .iter()
+        .filter_map(|container| container.captures(&page))
+        .map(|captures| paragraphs(&captures[1]))
+        .chain(std::iter::once_with(|| paragraphs(&page)))
+        .find(|paragraphs| !paragraphs.is_empty())
+        .map(|paragraphs| paragraphs.join("\n\n"))
+}
+
+/// Returns the text of the content paragraphs.
+fn paragraphs(html: &str) -> Vec<String> {
+    PARAGRAPH_RE
+        .captures_iter(html)
+        .filter_map(|captures| {
+            let paragraph = &captures[1];
+            let text = text(paragraph);
+            let length = text.chars().count();
+            let link_length: usize = LINK_RE
+                .captures_iter(paragraph)
+                .map(|link| text_of(&link[1]).chars().count())
+                .sum();
+            (length >= MIN_PARAGRAPH_LENGTH
+                && link_length as f32 <= length as f32 * MAX_LINK_DENSITY)
+                .then_some(text)
+        })
+        .collect()
+}
+
+/// Returns the text of the HTML, the whitespace is collapsed.
+fn text(html: &str) -> String {
+    text_of(html)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the unescaped text of the HTML, without its tags.
+fn text_of(html: &str) -> String {
+    let html = html.replace("<br", " <br");
+    let text = TAG_RE.replace_all(&html, "");
+    quick_xml::escape::unescape(&text)
+        .map(|text| text.into_owned())
+        .unwrap_or_else(|_| text.into_owned())
+}
+
+/// Truncates the text to at most `max_size` bytes at a word boundary,
+/// the truncated text ends with `…`.
+fn truncate(text: &str, max_size: usize) -> String {
+    if text.len() <= max_size {
+        return text.to_owned();
+    }
+    let mut end = max_size.saturating_sub('…'.len_utf8());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let text = &text[..end];
+    let text = text
+        .rfind(char::is_whitespace)
+        .map_or(text, |space| &text[..space]);
+    format!("{}…", text.trim_end())
+}
@@ -1,35 +1,43 @@
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-use megalodon::entities::{Attachment, UploadMedia};
+use megalodon::{
+    entities::{Attachment, UploadMedia},
+    megalodon::UploadMediaInputOptions,
+};
 
 use crate::{
     config::Config,
     errors::{Error as PError, Result as PResult},
+    metrics,
 };
 
-/// Get the image id from the image path
-pub async fn get_image_id(image_path: PathBuf, config: &Config) -> PResult<String> {
-    log::info!("Uploading image: {}", image_path.display());
-    let client = megalodon::generator(
-        config.sns(),
-        config
-            .base_url
-            .to_string()
-            .trim_end_matches('/')
-            .to_string(),
-        Some(config.bot_token.clone()),
-        None,
-    );
+/// Get the image id from the image path, the image is uploaded with the alt text.
+/// An empty alt text is not sent.
+pub async fn get_image_id(
+    image_path: PathBuf,
+    alt_text: &str,
+    client: &(dyn megalodon::Megalodon + Send + Sync),
+    config: &Config,
+) -> PResult<String> {
+    tracing::info!("Uploading image: {}", image_path.display());
+    let _timer = metrics::MEDIA_UPLOAD_DURATION.start_timer();
+    let options = UploadMediaInputOptions {
+        description: (!alt_text.is_empty()).then(|| alt_text.to_owned()),
+        focus: None,
+    };
     let res = client
-        .upload_media(image_path.display().to_string(), None)
+        .upload_media(image_path.display().to_string(), Some(&options))
         .await?;
     match res.json() {
         UploadMedia::Attachment(media) => Ok(media.id),
         UploadMedia::AsyncAttachment(media) => {
-            match wait_image_upload(client.as_ref(), &media.id).await {
+            match wait_image_upload(client, &media.id, config).await {
                 Ok(media) => Ok(media.id),
                 Err(e) => {
-                    log::error!("Error uploading image: {}", e);
+                    tracing::error!("Error uploading image: {}", e);
                     Err(e)
                 }
             }
@@ -38,32 +46,42 @@ pub async fn get_image_id(image_path: PathBuf, config: &Config) -> PResult<Strin
 }
 
 /// Wait the image to be uploaded to mastodon.
+/// The media is polled every `--media-poll-interval` seconds, the interval is multiplied
+/// by `--media-poll-backoff` after every poll, until the `--media-timeout` deadline.
 pub async fn wait_image_upload(
     client: &(dyn megalodon::Megalodon + Send + Sync),
     image_id: &str,
+    config: &Config,
 ) -> PResult<Attachment> {
-    log::info!("Waiting for image to be uploaded. Image ID: {}", image_id);
+    tracing::info!("Waiting for image to be uploaded. Image ID: {}", image_id);
+    let deadline = Instant::now() + Duration::from_secs(config.media_timeout);
+    let mut interval = Duration::from_secs(config.media_poll_interval);
     let mut tries = 0;
     loop {
         tries += 1;
-        log::debug!("Trying number: {}", tries);
+        tracing::debug!("Trying number: {}", tries);
         let res = client.get_media(image_id.to_owned()).await;
-        if tries > 5 {
-            log::error!("Image upload timeout. Image ID: {}", image_id);
-            return Err(PError::ImageTimeout(image_id.to_owned()));
-        }
         match res {
             Ok(media) => {
-                log::info!("Image uploaded successfully. Image ID: {}", image_id);
+                tracing::info!("Image uploaded successfully. Image ID: {}", image_id);
                 return Ok(media.json());
             }
             Err(err) => match err {
                 megalodon::error::Error::OwnError(ref own_err) => match own_err.kind {
-                    megalodon::error::Kind::HTTPPartialContentError => continue,
+                    megalodon::error::Kind::HTTPPartialContentError => {}
                     _ => return Err(err.into()),
                 },
                 _ => return Err(err.into()),
             },
         }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            tracing::error!("Image upload timeout. Image ID: {}", image_id);
+            metrics::MEDIA_TIMEOUTS.inc();
+            return Err(PError::ImageTimeout(image_id.to_owned()));
+        }
+        // The last poll is at the deadline.
+        tokio::time::sleep(interval.min(remaining)).await;
+        interval = interval.mul_f64(config.media_poll_backoff);
     }
 }
@@ -0,0 +1,204 @@
+use std::{
+    fs,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use image::{io::Reader, ImageFormat};
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::{link_preview::CLIENT, public};
+use crate::{
+    config::Config,
+    errors::{Error as PError, Result as PResult},
+};
+
+/// Returns the cached item image, the image is downloaded if it is not cached or expired.
+/// The image is rejected if it is larger than `--max-image-size`, takes more than
+/// `--image-timeout` to download, or does not have a valid PNG, JPEG, GIF or WebP header.
+/// With `public_only`, the image of a user feed is only downloaded from the public addresses.
+pub async fn fetch(url: &str, public_only: bool, config: &Config) -> PResult<PathBuf> {
+    // The directory is checked before a cached image is used, it may be replaced.
+    create_dir(&config.image_cache_dir)?;
+    let path = config.image_cache_dir.join(cache_key(url));
+    let ttl = Duration::from_secs(config.image_cache_ttl);
+    if !is_expired(&path, ttl) {
+        tracing::debug!("Using the cached image of {}: {}", url, path.display());
+        return Ok(path);
+    }
+    tracing::info!("Downloading the item image: {}", url);
+    let data = download(url, public_only, config).await?;
+    prune(&config.image_cache_dir, ttl);
+    // Written to a new temporary file first, so a partial image is never used.
+    // The temporary file is only readable by the bot user, and is removed if it is not persisted.
+    let mut file = tempfile::NamedTempFile::new_in(&config.image_cache_dir)?;
+    file.write_all(&data)?;
+    file.persist(&path).map_err(|err| err.error)?;
+    Ok(path)
+}
+
+/// Creates the cache directory, only the bot user can access the created directories.
+/// A directory that is not owned by the bot user, or is a symbolic link, is refused,
+/// so the other users can not replace the cached images.
+fn create_dir(dir: &Path) -> PResult<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)?;
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() {
+        return Err(PError::Preview(format!(
+            "The image cache directory {} is not a directory",
+            dir.display()
+        )));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // SAFETY: `geteuid` has no preconditions and never fails.
+        let uid = unsafe { libc::geteuid() };
+        if metadata.uid() != uid {
+            return Err(PError::Preview(format!(
+                "The image cache directory {} is not owned by the bot user",
+                dir.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Downloads the image with the size, time and type limits.
+async fn download(url: &str, public_only: bool, config: &Config) -> PResult<Vec<u8>> {
+    let invalid =
+        |reason: String| PError::Preview(format!("The image {url} is rejected: {reason}"));
+    let client = if public_only {
+        let parsed = Url::parse(url)?;
+        if !public::is_public_url(&parsed) {
+            return Err(PError::NotPublic(parsed));
+        }
+        &*public::CLIENT
+    } else {
+        &*CLIENT
+    };
+    let mut res = client
+        .get(url)
+        .timeout(Duration::from_secs(config.image_timeout))
+        .send()
+        .await?
+        .error_for_status()?;
+    if let Some(content_type) = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    {
+        if !content_type.starts_with("image/") {
+            return Err(invalid(format!("the content type is {content_type}")));
+        }
+    }
+    let too_large = || invalid(format!("it is larger than {} bytes", config.max_image_size));
+    if res
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<u64>().ok())
+        .is_some_and(|length| length > config.max_image_size)
+    {
+        return Err(too_large());
+    }
+    let mut data = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        data.extend_from_slice(&chunk);
+        if data.len() as u64 > config.max_image_size {
+            return Err(too_large());
+        }
+    }
+    let (format, (width, height)) = read_header(&data).map_err(invalid)?;
+    tracing::debug!(
+        "The image {} is a {}x{} {:?} image.",
+        url,
+        width,
+        height,
+        format
+    );
+    Ok(data)
+}
+
+/// Reads the header of the image, returns its format and size.
+/// Only the PNG, JPEG, GIF and WebP images with a valid header are accepted.
+fn read_header(data: &[u8]) -> Result<(ImageFormat, (u32, u32)), String> {
+    let reader = Reader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|err| err.to_string())?;
+    let format = match reader.format() {
+        Some(
+            format @ (ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP),
+        ) => format,
+        _ => return Err("it is not a PNG, JPEG, GIF or WebP image".to_owned()),
+    };
+    let size = reader
+        .into_dimensions()
+        .map_err(|err| format!("the image header is invalid: {err}"))?;
+    Ok((format, size))
+}
+
+/// Returns the cache file name of the url, the hex SHA-256 of the url.
+fn cache_key(url: &str) -> String {
+    Sha256::digest(url.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Returns `true` if the cached file does not exist or is older than the ttl.
+fn is_expired(path: &Path, ttl: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_none_or(|age| age > ttl)
+}
+
+/// Removes the expired images from the cache.
+fn prune(dir: &Path, ttl: Duration) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if is_expired(&path, ttl) {
+            tracing::debug!("Removing the expired image: {}", path.display());
+            if let Err(err) = fs::remove_file(&path) {
+                tracing::warn!(
+                    "Error removing the expired image {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_header() {
+        let mut png = Vec::new();
+        image::RgbImage::new(3, 2)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        assert_eq!(read_header(&png), Ok((ImageFormat::Png, (3, 2))));
+    }
+
+    #[test]
+    fn invalid_headers() {
+        // Only the magic bytes of a PNG, without its header.
+        assert!(read_header(b"\x89PNG\r\n\x1a\n").is_err());
+        assert!(read_header(b"<html></html>").is_err());
+        assert!(read_header(b"BM").is_err());
+    }
+}
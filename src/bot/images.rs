@@ -0,0 +1,118 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use url::Url;
+
+use super::link_preview::{self, attribute};
+use crate::{cli::ImageSource, config::Config, errors::Result as PResult, utils::remove_html_tags};
+
+/// Matches the `<img>` tags.
+static IMG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<img\s[^>]*>").unwrap());
+
+/// Returns the images of the item in the feed, with their sources, in the order of the feed.
+/// Only the `media:content` images are returned, e.g. not the videos of a podcast.
+/// The relative urls are resolved with the item link, and only the `http` and `https`
+/// images are returned, a local path may be a private file.
+pub fn discover(item: &rss::Item, link: &str) -> Vec<(ImageSource, String)> {
+    let mut images = Vec::new();
+    let media = item.extensions().get("media");
+    let media_url = |name: &str| {
+        media
+            .and_then(|media| media.get(name))
+            .and_then(|elements| elements.first())
+            .and_then(|element| element.attrs.get("url"))
+            .map(String::as_str)
+    };
+    images.extend(
+        media
+            .and_then(|media| media.get("content"))
+            .into_iter()
+            .flatten()
+            .filter(|element| is_image(element))
+            .filter_map(|element| element.attrs.get("url"))
+            .map(|url| (ImageSource::MediaContent, url.as_str())),
+    );
+    images.extend(
+        item.enclosure()
+            .filter(|enclosure| enclosure.mime_type().starts_with("image/"))
+            .map(|enclosure| (ImageSource::Enclosure, enclosure.url())),
+    );
+    images.extend(media_url("thumbnail").map(|url| (ImageSource::MediaThumbnail, url)));
+    images.extend(
+        item.itunes_ext()
+            .and_then(|itunes| itunes.image())
+            .map(|url| (ImageSource::ItunesImage, url)),
+    );
+    let inline = [item.description(), item.content()]
+        .into_iter()
+        .flatten()
+        .find_map(|html| {
+            IMG_RE
+                .find_iter(html)
+                .find_map(|tag| attribute(tag.as_str(), "src"))
+        });
+    let inline = inline.as_deref().map(|url| (ImageSource::Inline, url));
+    images
+        .into_iter()
+        .chain(inline)
+        .filter_map(|(source, url)| Some((source, absolute_url(link, url)?)))
+        .collect()
+}
+
+/// Returns `true` if the `media:content` is an image, by its `medium` or its `type`.
+fn is_image(content: &rss::extension::Extension) -> bool {
+    content
+        .attrs
+        .get("medium")
+        .is_some_and(|medium| medium == "image")
+        || content
+            .attrs
+            .get("type")
+            .is_some_and(|mime_type| mime_type.starts_with("image/"))
+}
+
+/// Returns the `media:description` of the item media, it describes the
+/// `media:content` and the `media:thumbnail` images.
+/// It is looked up in the `media:content`, then in the `media:group` and the item.
+pub fn media_description(item: &rss::Item) -> Option<String> {
+    let media = item.extensions().get("media")?;
+    let description_of = |element: &rss::extension::Extension| {
+        element
+            .children
+            .get("description")
+            .and_then(|descriptions| descriptions.first())
+            .and_then(|description| description.value.clone())
+    };
+    media
+        .get("content")
+        .into_iter()
+        .chain(media.get("group"))
+        .flatten()
+        .find_map(description_of)
+        .or_else(|| {
+            media
+                .get("description")
+                .and_then(|descriptions| descriptions.first())
+                .and_then(|description| description.value.clone())
+        })
+        .map(|description| remove_html_tags(&description))
+        .filter(|description| !description.is_empty())
+}
+
+/// Returns the `og:image` or the `twitter:image` of the item page.
+pub async fn page_image(link: &str, config: &Config) -> PResult<Option<String>> {
+    tracing::debug!("Looking for the page image of: {}", link);
+    let page = link_preview::fetch(link, &config.link_preview).await?;
+    Ok(page
+        .image
+        .as_deref()
+        .and_then(|url| absolute_url(link, url)))
+}
+
+/// Resolves the image url with the item link, returns `None` if it is not a remote image.
+fn absolute_url(link: &str, url: &str) -> Option<String> {
+    let url = match Url::parse(link) {
+        Ok(link) => link.join(url.trim()).ok()?,
+        Err(_) => Url::parse(url.trim()).ok()?,
+    };
+    matches!(url.scheme(), "http" | "https").then(|| url.to_string())
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::DateTime;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use url::Url;
+
+use crate::errors::Result as PResult;
+
+/// The maximum size of the page that is read, the metadata is in the page head.
+const MAX_PAGE_SIZE: usize = 1024 * 1024;
+
+/// The pages and images client, unlike the feeds client it follows the redirects.
+pub(super) static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+/// The metadata of the fetched pages, with their fetch time.
+static CACHE: Lazy<Mutex<Cache>> = Lazy::new(Default::default);
+/// The time of the next allowed request to each domain.
+static TURNS: Lazy<Mutex<HashMap<String, Instant>>> = Lazy::new(Default::default);
+
+/// Matches the `<meta>` tags.
+static META_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<meta\s[^>]*>").unwrap());
+/// Matches the `<title>` tag.
+static TITLE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+/// Matches the attributes of a tag.
+static ATTR_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"([\w:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+
+/// The cached page metadata, by the page link.
+type Cache = HashMap<String, (Instant, Arc<PageMeta>)>;
+
+/// The link preview options.
+#[derive(Debug, Clone)]
+pub struct LinkPreviewOptions {
+    /// Fill the missing fields of the items from their pages.
+    pub enabled: bool,
+    /// The maximum time to download a page in seconds.
+    pub timeout: u64,
+    /// The time to keep the metadata of a page in seconds.
+    pub cache_ttl: u64,
+    /// The minimum time between the requests to the same domain in seconds.
+    pub interval: u64,
+}
+
+/// The metadata of an article page, from its Open Graph tags.
+#[derive(Debug, Default)]
+pub struct PageMeta {
+    /// The `og:title`, or the page title.
+    pub title: Option<String>,
+    /// The `og:description`, or the page description.
+    pub description: Option<String>,
+    /// The `og:image`, or the `twitter:image`. It may be relative to the page.
+    #[cfg(feature = "preview-image")]
+    pub image: Option<String>,
+    /// The `article:author`.
+    #[cfg(feature = "preview-image")]
+    pub author: Option<String>,
+    /// The `article:published_time`.
+    pub published_time: Option<String>,
+}
+
+impl PageMeta {
+    /// Parses the metadata of the page.
+    fn parse(page: &str) -> Self {
+        let mut meta = Self::default();
+        let mut fallback_description = None;
+        #[cfg(feature = "preview-image")]
+        let mut twitter_image = None;
+        for tag in META_RE.find_iter(page) {
+            let tag = tag.as_str();
+            let Some(content) = attribute(tag, "content").filter(|content| !content.is_empty())
+            else {
+                continue;
+            };
+            let name = attribute(tag, "property")
+                .or_else(|| attribute(tag, "name"))
+                .unwrap_or_default()
+                .to_lowercase();
+            let field = match name.as_str() {
+                "og:title" => &mut meta.title,
+                "og:description" => &mut meta.description,
+                "description" => &mut fallback_description,
+                #[cfg(feature = "preview-image")]
+                "og:image" | "og:image:url" | "og:image:secure_url" => &mut meta.image,
+                #[cfg(feature = "preview-image")]
+                "twitter:image" | "twitter:image:src" => &mut twitter_image,
+                #[cfg(feature = "preview-image")]
+                "article:author" => &mut meta.author,
+                "article:published_time" => &mut meta.published_time,
+                _ => continue,
+            };
+            // The first tag is used, e.g. the first of the multiple images.
+            field.get_or_insert(content);
+        }
+        meta.title = meta.title.or_else(|| {
+            TITLE_RE
+                .captures(page)
+                .map(|captures| unescape(captures[1].trim()))
+                .filter(|title| !title.is_empty())
+        });
+        meta.description = meta.description.or(fallback_description);
+        #[cfg(feature = "preview-image")]
+        {
+            meta.image = meta.image.or(twitter_image);
+        }
+        meta
+    }
+
+    /// Returns the `article:published_time` as a timestamp.
+    pub fn published(&self) -> Option<u64> {
+        let time = self.published_time.as_deref()?;
+        DateTime::parse_from_rfc3339(time)
+            .or_else(|_| DateTime::parse_from_rfc2822(time))
+            .ok()
+            .map(|time| time.timestamp() as u64)
+    }
+}
+
+/// Returns the metadata of the page, it is cached for `cache_ttl` seconds.
+/// The requests to the same domain are sent at least `interval` seconds apart.
+pub async fn fetch(link: &str, options: &LinkPreviewOptions) -> PResult<Arc<PageMeta>> {
+    let ttl = Duration::from_secs(options.cache_ttl);
+    if let Some(meta) = cached(link, ttl) {
+        tracing::debug!("Using the cached metadata of the page: {}", link);
+        return Ok(meta);
+    }
+    if let Ok(url) = Url::parse(link) {
+        wait_turn(&url, Duration::from_secs(options.interval)).await;
+    }
+    tracing::info!("Fetching the page metadata of: {}", link);
+    let mut res = CLIENT
+        .get(link)
+        .timeout(Duration::from_secs(options.timeout))
+        .send()
+        .await?
+        .error_for_status()?;
+    let mut page = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        page.extend_from_slice(&chunk);
+        if page.len() > MAX_PAGE_SIZE {
+            break;
+        }
+    }
+    let meta = Arc::new(PageMeta::parse(&String::from_utf8_lossy(&page)));
+    tracing::debug!("The metadata of the page {}: {:?}", link, meta);
+    let mut cache = CACHE.lock().unwrap_or_else(|err| err.into_inner());
+    cache.retain(|_, (fetched, _)| fetched.elapsed() <= ttl);
+    cache.insert(link.to_owned(), (Instant::now(), Arc::clone(&meta)));
+    Ok(meta)
+}
+
+/// Returns the cached metadata of the page, if it is not expired.
+fn cached(link: &str, ttl: Duration) -> Option<Arc<PageMeta>> {
+    let cache = CACHE.lock().unwrap_or_else(|err| err.into_inner());
+    cache
+        .get(link)
+        .filter(|(fetched, _)| fetched.elapsed() <= ttl)
+        .map(|(_, meta)| Arc::clone(meta))
+}
+
+/// Waits for the turn of the request to the url domain.
+pub(super) async fn wait_turn(url: &Url, interval: Duration) {
+    let Some(domain) = url.host_str() else {
+        return;
+    };
+    let turn = {
+        let mut turns = TURNS.lock().unwrap_or_else(|err| err.into_inner());
+        let now = Instant::now();
+        let turn = turns.get(domain).map_or(now, |turn| (*turn).max(now));
+        turns.insert(domain.to_owned(), turn + interval);
+        turn
+    };
+    if turn > Instant::now() {
+        tracing::debug!("Waiting for the turn of the domain: {}", domain);
+        tokio::time::sleep_until(turn.into()).await;
+    }
+}
+
+/// Returns the unescaped value of the tag attribute.
+pub(super) fn attribute(tag: &str, name: &str) -> Option<String> {
+    ATTR_RE
+        .captures_iter(tag)
+        .find(|captures| captures[1].eq_ignore_ascii_case(name))
+        .and_then(|captures| captures.get(2).or_else(|| captures.get(3)))
+        .map(|value| unescape(value.as_str()))
+}
+
+/// Unescapes the HTML text, it is kept as is if it has an unknown entity.
+fn unescape(text: &str) -> String {
+    quick_xml::escape::unescape(text)
+        .map(|text| text.into_owned())
+        .unwrap_or_else(|_| text.to_owned())
+}
@@ -0,0 +1,330 @@
+use megalodon::{
+    entities::{notification::NotificationType, StatusVisibility},
+    megalodon::{GetNotificationsInputOptions, PostStatusInputOptions},
+    Megalodon,
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use url::Url;
+
+use crate::{
+    config::{Account, Config},
+    errors::Result as PResult,
+    utils,
+};
+
+/// The maximum length of a reply, longer replies are truncated.
+const MAX_REPLY_LENGTH: usize = 450;
+/// The maximum number of mentions that are fetched in one poll.
+const MAX_MENTIONS: u32 = 40;
+/// The maximum number of failed subscriptions of a subscriber in [`FAILED_ATTEMPTS_WINDOW`].
+pub const MAX_FAILED_SUBSCRIBES: usize = 5;
+/// The time that a failed subscription counts against the subscriber.
+const FAILED_ATTEMPTS_WINDOW: Duration = Duration::from_secs(60 * 60);
+/// The usage of the commands, sent as a reply to unknown commands.
+const USAGE: &str = "Commands: add <url> [accounts=..] [tags=..] [full_text=..], remove <url>, list, pause <url>, resume <url>, status";
+/// The reply of the commands that change an OPML feeds file.
+const OPML_REPLY: &str = "The feeds file is an OPML file, it can not be changed by the commands.";
+/// The usage of the subscriber commands, sent as a reply to unknown commands.
+const SUBSCRIBER_USAGE: &str = "Commands: subscribe <url>, unsubscribe <url>, list";
+
+/// A command sent to the bot by mentioning it.
+#[derive(Debug)]
+pub enum Command {
+    /// Adds a feed, the feeds file line (the url followed by the feed options).
+    Add(String),
+    /// Removes a feed.
+    Remove(Url),
+    /// Lists the feeds.
+    List,
+    /// Stops checking a feed.
+    Pause(Url),
+    /// Checks a paused or disabled feed again.
+    Resume(Url),
+    /// Shows the feeds status.
+    Status,
+}
+
+/// A command sent to the bot by a subscriber.
+#[derive(Debug)]
+pub enum SubscriberCommand {
+    /// Sends the new items of the feed to the subscriber.
+    Subscribe(Url),
+    /// Stops sending the items of the feed to the subscriber.
+    Unsubscribe(Url),
+    /// Lists the subscriptions of the subscriber.
+    List,
+}
+
+/// A mention of the bot account.
+#[derive(Debug)]
+pub struct Mention {
+    /// The notification id.
+    pub id: String,
+    /// The id of the status that mentions the bot.
+    pub status_id: String,
+    /// The account of the sender, e.g. `user@example.com` or `user` for the local accounts.
+    pub acct: String,
+    /// The status text, without the HTML tags.
+    pub text: String,
+    /// Whether the status is a direct message.
+    pub is_direct: bool,
+}
+
+/// The recent failed attempts of the users, e.g. the subscriptions to unusable feeds.
+/// It keeps a user from making the bot request many urls.
+#[derive(Debug, Default)]
+pub struct FailedAttempts(HashMap<String, Vec<Instant>>);
+
+impl FailedAttempts {
+    /// Returns the number of the recent failed attempts of the user.
+    pub fn count(&mut self, acct: &str) -> usize {
+        self.0.retain(|_, attempts| {
+            attempts.retain(|attempt| attempt.elapsed() < FAILED_ATTEMPTS_WINDOW);
+            !attempts.is_empty()
+        });
+        self.0.get(acct).map_or(0, Vec::len)
+    }
+
+    /// Records a failed attempt of the user.
+    pub fn add(&mut self, acct: &str) {
+        self.0
+            .entry(acct.to_owned())
+            .or_default()
+            .push(Instant::now());
+    }
+}
+
+/// Returns the words of the command, the mentions in the text are ignored.
+fn words(text: &str) -> Vec<&str> {
+    text.split_whitespace()
+        .filter(|word| !word.starts_with('@'))
+        .collect()
+}
+
+/// Returns the feed url of the command, the second word.
+fn url_arg(words: &[&str]) -> Result<Url, String> {
+    words
+        .get(1)
+        .ok_or_else(|| "The feed url is missing.".to_owned())
+        .and_then(|url| Url::parse(url).map_err(|err| format!("Invalid url: {err}")))
+}
+
+impl SubscriberCommand {
+    /// Parses the subscriber command, returns the usage if it is not a subscriber command.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let words = words(text);
+        match words.first().map(|word| word.to_lowercase()).as_deref() {
+            Some("subscribe") => url_arg(&words).map(Self::Subscribe),
+            Some("unsubscribe") => url_arg(&words).map(Self::Unsubscribe),
+            Some("list") => Ok(Self::List),
+            _ => Err(SUBSCRIBER_USAGE.to_owned()),
+        }
+    }
+}
+
+impl Command {
+    /// Parses the command, the mentions in the text are ignored.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let words = words(text);
+        let url = || url_arg(&words);
+        match words.first().map(|word| word.to_lowercase()).as_deref() {
+            Some("add") if words.len() > 1 => Ok(Self::Add(words[1..].join(" "))),
+            Some("add") => Err("The feed url is missing.".to_owned()),
+            Some("remove") => url().map(Self::Remove),
+            Some("list") => Ok(Self::List),
+            Some("pause") => url().map(Self::Pause),
+            Some("resume") => url().map(Self::Resume),
+            Some("status") => Ok(Self::Status),
+            _ => Err(USAGE.to_owned()),
+        }
+    }
+
+    /// Applies the command to the feeds, the feeds file and the state file are updated.
+    /// Returns the reply to the sender.
+    pub fn apply(self, config: &mut Config) -> PResult<String> {
+        let reply = match self {
+            Self::Add(line) => {
+                let feed = match utils::parse_feed(&line, true) {
+                    Ok(feed) => feed,
+                    Err(err) => return Ok(err.to_string()),
+                };
+                if utils::is_opml_file(&config.feeds_file) {
+                    return Ok(OPML_REPLY.to_owned());
+                }
+                if config.feeds.iter().any(|old| old.url == feed.url) {
+                    return Ok(format!("The feed {} is already added.", feed.url));
+                }
+                if let Some(name) = feed
+                    .accounts
+                    .iter()
+                    .find(|name| !config.accounts.iter().any(|account| &account.name == *name))
+                {
+                    return Ok(format!("There is no account named {name}."));
+                }
+                let reply = format!("The feed {} is added.", feed.url);
+                config.feeds.push(feed);
+                utils::write_feeds(&config.feeds_file, &config.feeds)?;
+                reply
+            }
+            Self::Remove(_) if utils::is_opml_file(&config.feeds_file) => OPML_REPLY.to_owned(),
+            Self::Remove(url) => {
+                let count = config.feeds.len();
+                config.feeds.retain(|feed| feed.url != url);
+                if config.feeds.len() == count {
+                    return Ok(format!("The feed {url} is not found."));
+                }
+                utils::write_feeds(&config.feeds_file, &config.feeds)?;
+                format!("The feed {url} is removed.")
+            }
+            Self::List if config.feeds.is_empty() => "There are no feeds.".to_owned(),
+            Self::List => config
+                .feeds
+                .iter()
+                .map(|feed| {
+                    if feed.disabled {
+                        format!("{feed} (paused)")
+                    } else if feed.failures > 0 {
+                        format!("{feed} ({} failure(s))", feed.failures)
+                    } else {
+                        feed.to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::Pause(url) | Self::Resume(url) if !config.feeds.iter().any(|f| f.url == url) => {
+                return Ok(format!("The feed {url} is not found."));
+            }
+            Self::Pause(url) => {
+                if let Some(feed) = config.feeds.iter_mut().find(|feed| feed.url == url) {
+                    feed.disabled = true;
+                }
+                config.save_state()?;
+                format!("The feed {url} is paused.")
+            }
+            Self::Resume(url) => {
+                if let Some(feed) = config.feeds.iter_mut().find(|feed| feed.url == url) {
+                    feed.disabled = false;
+                    feed.failures = 0;
+                    feed.retry_at = None;
+                }
+                config.save_state()?;
+                format!("The feed {url} is resumed.")
+            }
+            Self::Status => {
+                let paused = config.feeds.iter().filter(|feed| feed.disabled).count();
+                let failing = config
+                    .feeds
+                    .iter()
+                    .filter(|feed| !feed.disabled && feed.failures > 0)
+                    .count();
+                format!(
+                    "{} feed(s), {} paused or disabled, {} failing. {} account(s).",
+                    config.feeds.len(),
+                    paused,
+                    failing,
+                    config.accounts.len()
+                )
+            }
+        };
+        Ok(reply)
+    }
+}
+
+/// Returns `true` if the sender is in the allowed accounts.
+/// The local senders are matched with the domain of the bot account.
+pub fn is_allowed(allowed: &[String], acct: &str, account: &Account) -> bool {
+    let acct = if acct.contains('@') {
+        acct.to_lowercase()
+    } else {
+        format!(
+            "{}@{}",
+            acct,
+            account.base_url.host_str().unwrap_or_default()
+        )
+        .to_lowercase()
+    };
+    allowed
+        .iter()
+        .any(|allowed| allowed.trim_start_matches('@').to_lowercase() == acct)
+}
+
+/// Fetches the mentions of the account, the oldest first.
+pub async fn fetch(client: &(dyn Megalodon + Send + Sync)) -> PResult<Vec<Mention>> {
+    let options = GetNotificationsInputOptions {
+        limit: Some(MAX_MENTIONS),
+        exclude_types: Some(vec![
+            NotificationType::Follow,
+            NotificationType::FollowRequest,
+            NotificationType::Reblog,
+            NotificationType::Favourite,
+            NotificationType::PollVote,
+            NotificationType::PollExpired,
+            NotificationType::Status,
+            NotificationType::EmojiReaction,
+        ]),
+        ..Default::default()
+    };
+    let notifications = client.get_notifications(Some(&options)).await?.json;
+    Ok(notifications
+        .into_iter()
+        .rev()
+        .filter(|notification| matches!(notification.r#type, NotificationType::Mention))
+        .filter_map(|notification| {
+            let status = notification.status?;
+            Some(Mention {
+                id: notification.id,
+                text: status_text(&status.content),
+                status_id: status.id,
+                acct: notification.account.acct,
+                is_direct: matches!(status.visibility, StatusVisibility::Direct),
+            })
+        })
+        .collect())
+}
+
+/// Returns the text of the status HTML content.
+/// Unlike [`utils::remove_html_tags`], the entities are unescaped to keep the urls valid.
+fn status_text(content: &str) -> String {
+    let tag_re = regex::Regex::new(r"<[^>]*>").unwrap();
+    let content = content.replace("<br", " <br").replace("<p", " <p");
+    let text = tag_re.replace_all(&content, "");
+    quick_xml::escape::unescape(&text)
+        .map(|text| text.into_owned())
+        .unwrap_or_else(|_| text.into_owned())
+}
+
+/// Dismisses the mention notification.
+pub async fn dismiss(client: &(dyn Megalodon + Send + Sync), mention: &Mention) -> PResult<()> {
+    match client.dismiss_notification(mention.id.clone()).await {
+        // The instances return `{}`, but megalodon expects an empty body.
+        Ok(_) => Ok(()),
+        Err(megalodon::error::Error::RequestError(err)) if err.is_decode() => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Replies to the mention with a direct message.
+pub async fn reply(
+    client: &(dyn Megalodon + Send + Sync),
+    mention: &Mention,
+    reply: &str,
+) -> PResult<()> {
+    let mut status = format!("@{} {}", mention.acct, reply);
+    if status.chars().count() > MAX_REPLY_LENGTH {
+        status = status
+            .chars()
+            .take(MAX_REPLY_LENGTH - 1)
+            .collect::<String>()
+            + "…";
+    }
+    let options = PostStatusInputOptions {
+        in_reply_to_id: Some(mention.status_id.clone()),
+        visibility: Some(StatusVisibility::Direct),
+        ..Default::default()
+    };
+    client.post_status(status, Some(&options)).await?;
+    Ok(())
+}
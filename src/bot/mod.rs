@@ -1,22 +1,84 @@
-pub use self::rss::Feed;
-use crate::{cli::Cli, config::Config, errors::Error as PError, errors::Result as PResult};
+use self::rss::Content;
+pub use self::{link_preview::LinkPreviewOptions, rss::Feed};
+use self::{
+    notifier::Notifier, poster::Poster, shutdown::Shutdown, status::Status, watcher::Watcher,
+};
+use crate::{
+    cli::RunArgs,
+    config::Config,
+    errors::Error as PError,
+    errors::Result as PResult,
+    metrics,
+    state::FeedState,
+    subscriptions::{Subscriber, Subscriptions},
+};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{sync::mpsc, task::JoinHandle};
+mod full_text;
 #[cfg(feature = "preview-image")]
 mod image;
+#[cfg(feature = "preview-image")]
+mod image_cache;
+#[cfg(feature = "preview-image")]
+mod images;
+mod link_preview;
+mod mentions;
+mod notifier;
+mod poster;
+#[cfg(feature = "native-preview")]
+mod preview;
+mod public;
 mod rss;
+mod server;
+mod shutdown;
+mod status;
+#[cfg(feature = "preview-image")]
+mod svg;
+mod watcher;
 
-/// A bot struct that handles the communication with the pleroma instance.
+/// A bot struct that handles the communication with the pleroma instances.
 /// It also handles the RSS feed parsing.
 #[derive(Debug)]
 pub struct Bot {
     config: Config,
+    /// The posting queues of the accounts.
+    posters: Vec<Poster>,
+    /// The closing posting queues of the removed or changed accounts.
+    /// They return their abandoned contents.
+    closing: Vec<JoinHandle<Vec<Content>>>,
+    /// The shutdown signal.
+    shutdown: Shutdown,
+    /// The runtime status of the bot.
+    status: Status,
+    /// The sender of the fatal errors of the posters.
+    errors_sender: mpsc::UnboundedSender<PError>,
+    /// The fatal errors of the posters, e.g. a rejected access token.
+    errors: mpsc::UnboundedReceiver<PError>,
+    /// The admin notifier.
+    notifier: Notifier,
+    /// The subscriptions of the users.
+    subscriptions: Subscriptions,
+    /// The subscribed feeds by their url, they keep the backoff of the failing feeds
+    /// between the polls. Their persisted state is in the subscriptions.
+    subscribed_feeds: HashMap<String, Feed>,
+    /// The accounts that can not read their notifications, their commands are not read.
+    /// The access token of an account needs the notifications scopes to read the commands.
+    no_commands: Vec<String>,
+    /// The recent failed subscriptions of the subscribers.
+    failed_subscribes: mentions::FailedAttempts,
 }
 
 impl Bot {
-    /// Creates a new bot.
-    pub fn new(config: Config) -> PResult<Self> {
-        log::debug!(
-            "Creating a new bot. The base url is: {}. The feeds is: {}",
-            config.base_url,
+    /// Creates a new bot, and spawns the posting queue of every account.
+    pub fn new(config: Config, shutdown: Shutdown) -> PResult<Self> {
+        tracing::debug!(
+            "Creating a new bot. The accounts are: {}. The feeds is: {}",
+            config
+                .accounts
+                .iter()
+                .map(|a| format!("{} ({})", a.name, a.base_url))
+                .collect::<Vec<String>>()
+                .join(", "),
             config
                 .feeds
                 .iter()
@@ -24,59 +86,710 @@ impl Bot {
                 .collect::<Vec<String>>()
                 .join(", ")
         );
-        Ok(Self { config })
+        let subscriptions = match &config.subscriptions_file {
+            Some(file) => Subscriptions::read(file)?,
+            None => Subscriptions::default(),
+        };
+        let shared_config = Arc::new(config.clone());
+        let status = Status::new(&config.accounts, &config.feeds);
+        let (errors_sender, errors) = mpsc::unbounded_channel();
+        let notifier = Notifier::spawn(
+            config.admin.clone(),
+            Duration::from_secs(config.admin_interval),
+            config.dry_run,
+            status.clone(),
+        );
+        let posters = config
+            .accounts
+            .iter()
+            .map(|account| {
+                Poster::spawn(
+                    account.clone(),
+                    Arc::clone(&shared_config),
+                    shutdown.clone(),
+                    status.clone(),
+                    errors_sender.clone(),
+                    notifier.handle(),
+                )
+            })
+            .collect();
+        Ok(Self {
+            config,
+            posters,
+            closing: Vec::new(),
+            shutdown,
+            status,
+            errors_sender,
+            errors,
+            notifier,
+            subscriptions,
+            subscribed_feeds: HashMap::new(),
+            no_commands: Vec::new(),
+            failed_subscribes: mentions::FailedAttempts::default(),
+        })
     }
 
-    /// Posts the new content to the pleroma instance.
+    /// Posts the new contents of the feeds and the subscriptions, then handles the mentions.
+    pub async fn poll(&mut self) -> PResult<()> {
+        self.post_new_contents().await?;
+        self.post_subscriptions().await?;
+        self.handle_mentions().await
+    }
+
+    /// Sends the new content to the posting queues of the feed accounts.
+    #[tracing::instrument(name = "poll", skip_all)]
     pub async fn post_new_contents(&mut self) -> PResult<()> {
-        log::info!("Checking for new contents.");
-        let config = self.config.clone();
+        tracing::info!("Checking for new contents.");
+        let mut disabled = Vec::new();
         for feed in &mut self.config.feeds {
-            log::info!("Checking feed: {}", feed.url);
-            for content in &feed.check().await? {
-                log::info!("Found new content: {}", content.title);
-                if config.dry_run {
-                    log::info!("Dry run. Not posting.");
+            if !feed.is_due() {
+                tracing::debug!("Skipping the disabled or failing feed: {}", feed.url);
+                continue;
+            }
+            let contents = feed.check(&self.config.link_preview).await;
+            let result = if let Err(err) = &contents {
+                metrics::FEED_ERRORS
+                    .with_label_values(&[feed.url.as_str(), err.kind_name()])
+                    .inc();
+                if !err.is_fatal()
+                    && feed.record_failure(err, self.config.watting_new, self.config.max_failures)
+                {
+                    disabled.push(format!(
+                        "The feed {} is disabled after {} failure(s): {}",
+                        feed.url, feed.failures, err
+                    ));
+                }
+                "error"
+            } else {
+                feed.record_success();
+                "ok"
+            };
+            self.status
+                .feed_fetched(feed, contents.as_ref().err().map(ToString::to_string));
+            metrics::FEED_FETCHES
+                .with_label_values(&[feed.url.as_str(), result])
+                .inc();
+            let contents = match contents {
+                Ok(contents) => contents,
+                Err(err) if err.is_fatal() => return Err(err),
+                Err(err) => {
+                    // The other feeds are still checked.
+                    tracing::error!("Error checking the feed {}: {}", feed.url, err);
+                    eprintln!("Error checking the feed {}: {}", feed.url, err);
+                    continue;
+                }
+            };
+            let accounts = self
+                .config
+                .accounts
+                .iter()
+                .filter(|account| feed.is_routed_to(&account.name))
+                .map(|account| account.name.as_str())
+                .collect::<Vec<_>>();
+            for content in contents {
+                tracing::info!("Found new content: {}", content.title);
+                if self.config.dry_run {
+                    tracing::info!("Dry run. Not posting.");
+                    println!("Accounts: {}", accounts.join(", "));
                     println!("{content:#?}");
+                    continue;
+                }
+                for poster in self
+                    .posters
+                    .iter()
+                    .filter(|poster| accounts.contains(&poster.account.name.as_str()))
+                {
+                    poster.push(content.clone());
+                }
+            }
+        }
+        for message in disabled {
+            self.notifier.notify(message);
+        }
+        self.config.save_state()
+    }
+
+    /// Sends the new contents of the subscribed feeds to their subscribers as direct messages.
+    #[tracing::instrument(name = "subscriptions", skip_all)]
+    pub async fn post_subscriptions(&mut self) -> PResult<()> {
+        let Some(subscriptions_file) = &self.config.subscriptions_file else {
+            return Ok(());
+        };
+        let subscriptions = &mut self.subscriptions;
+        self.subscribed_feeds
+            .retain(|url, _| subscriptions.feeds.contains_key(url));
+        let mut disabled = Vec::new();
+        for (url, subscribed) in &mut self.subscriptions.feeds {
+            let feed = match self.subscribed_feeds.entry(url.clone()) {
+                std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                std::collections::hash_map::Entry::Vacant(entry) => {
+                    let Ok(url) = url.parse() else {
+                        continue;
+                    };
+                    let mut feed = Feed::new(url, false);
+                    feed.public_only = true;
+                    subscribed.state.restore(&mut feed);
+                    entry.insert(feed)
+                }
+            };
+            if !feed.is_due() {
+                tracing::debug!(
+                    "Skipping the disabled or failing subscribed feed: {}",
+                    feed.url
+                );
+                continue;
+            }
+            let result = feed.check(&self.config.link_preview).await;
+            let contents = match result {
+                Ok(contents) => {
+                    feed.record_success();
+                    contents
+                }
+                Err(err) if err.is_fatal() => return Err(err),
+                Err(err) => {
+                    tracing::warn!("Error checking the subscribed feed {}: {}", feed.url, err);
+                    if feed.record_failure(&err, self.config.watting_new, self.config.max_failures)
+                    {
+                        disabled.push(format!(
+                            "The subscribed feed {} is disabled after {} failure(s): {}",
+                            feed.url, feed.failures, err
+                        ));
+                    }
+                    subscribed.state = FeedState::of(feed);
+                    continue;
+                }
+            };
+            subscribed.state = FeedState::of(feed);
+            for content in contents {
+                for subscriber in &subscribed.subscribers {
+                    let content = Content {
+                        recipient: Some(subscriber.acct.clone()),
+                        ..content.clone()
+                    };
+                    if self.config.dry_run {
+                        tracing::info!("Dry run. Not sending to {}.", subscriber.acct);
+                        println!("Recipient: {} ({})", subscriber.acct, subscriber.account);
+                        println!("{content:#?}");
+                        continue;
+                    }
+                    match self
+                        .posters
+                        .iter()
+                        .find(|poster| poster.account.name == subscriber.account)
+                    {
+                        Some(poster) => poster.push(content),
+                        None => tracing::warn!(
+                            "The account {} of the subscriber {} is removed.",
+                            subscriber.account,
+                            subscriber.acct
+                        ),
+                    }
+                }
+            }
+        }
+        for message in disabled {
+            self.notifier.notify(message);
+        }
+        self.subscriptions.write(subscriptions_file)
+    }
+
+    /// Runs the commands of the subscribers, returns the reply.
+    async fn subscriber_command(
+        &mut self,
+        command: mentions::SubscriberCommand,
+        subscriber: Subscriber,
+    ) -> PResult<String> {
+        let Some(subscriptions_file) = &self.config.subscriptions_file else {
+            return Ok(String::new());
+        };
+        let subscribed = self.subscriptions.of(&subscriber);
+        let reply = match command {
+            mentions::SubscriberCommand::Subscribe(url) if subscribed.contains(&url.as_str()) => {
+                format!("You are already subscribed to {url}.")
+            }
+            mentions::SubscriberCommand::Subscribe(_)
+                if subscribed.len() >= self.config.max_subscriptions =>
+            {
+                format!(
+                    "You can subscribe to {} feed(s) at most.",
+                    self.config.max_subscriptions
+                )
+            }
+            mentions::SubscriberCommand::Subscribe(_)
+                if self.failed_subscribes.count(&subscriber.acct)
+                    >= mentions::MAX_FAILED_SUBSCRIBES =>
+            {
+                "You have too many failed subscriptions, try again later.".to_owned()
+            }
+            mentions::SubscriberCommand::Subscribe(url) => {
+                // Make sure that the feed is usable, and start from its newest item.
+                // The error is not sent to the user, it may leak what the bot can reach.
+                let mut feed = Feed::new(url, true);
+                feed.public_only = true;
+                let result = if public::resolves_to_public(&feed.url).await {
+                    feed.check(&self.config.link_preview).await.map(drop)
                 } else {
-                    content.post(&config).await?;
-                    log::info!(
-                        "Sleeping for {} seconds, before sending the next item.",
-                        self.config.items_sleep
+                    Err(PError::NotPublic(feed.url.clone()))
+                };
+                if let Err(err) = result {
+                    tracing::info!(
+                        "The subscription of {} to {} failed: {}",
+                        subscriber.acct,
+                        feed.url,
+                        err
+                    );
+                    self.failed_subscribes.add(&subscriber.acct);
+                    return Ok(format!(
+                        "The feed {} can not be used, make sure that it is a public RSS feed.",
+                        feed.url
+                    ));
+                }
+                // A disabled feed is enabled again, it has just been checked.
+                self.subscribed_feeds.remove(feed.url.as_str());
+                self.subscriptions
+                    .subscribe(feed.url.as_str(), subscriber, FeedState::of(&feed));
+                self.subscriptions.write(subscriptions_file)?;
+                format!("You are subscribed to {}.", feed.url)
+            }
+            mentions::SubscriberCommand::Unsubscribe(url) => {
+                if !self.subscriptions.unsubscribe(url.as_str(), &subscriber) {
+                    return Ok(format!("You are not subscribed to {url}."));
+                }
+                self.subscriptions.write(subscriptions_file)?;
+                format!("You are unsubscribed from {url}.")
+            }
+            mentions::SubscriberCommand::List if subscribed.is_empty() => {
+                "You have no subscriptions.".to_owned()
+            }
+            mentions::SubscriberCommand::List => subscribed.join("\n"),
+        };
+        Ok(reply)
+    }
+
+    /// Runs the commands that the users sent by mentioning the bot accounts,
+    /// and replies with the results. The handled mentions are dismissed.
+    /// The allowed accounts can manage the feeds, and the other users can subscribe
+    /// to the feeds if there is a subscriptions file.
+    #[tracing::instrument(name = "mentions", skip_all)]
+    pub async fn handle_mentions(&mut self) -> PResult<()> {
+        let has_subscriptions = self.config.subscriptions_file.is_some();
+        if self.config.allowed.is_empty() && !has_subscriptions {
+            return Ok(());
+        }
+        for account in self.config.accounts.clone() {
+            if self.no_commands.contains(&account.name) {
+                continue;
+            }
+            let client = account.client();
+            let mentions = match mentions::fetch(client.as_ref()).await {
+                Ok(mentions) => mentions,
+                // The token of the account misses the notifications scopes, the posting still works.
+                // A rejected access token stops the bot.
+                Err(err) if matches!(err.instance_status(), Some(401 | 403)) => {
+                    account.verify_token(client.as_ref()).await?;
+                    tracing::warn!(
+                        "The account {} can not read its notifications, its commands are disabled. \
+                         Run `login --commands` to get the notifications scopes: {}",
+                        account.name,
+                        err
                     );
-                    tokio::time::sleep(std::time::Duration::from_secs(self.config.items_sleep))
-                        .await;
+                    self.no_commands.push(account.name.clone());
+                    continue;
                 }
+                Err(err) => return Err(err),
+            };
+            for mention in mentions {
+                // Dismiss it first, so a failing command is not run again.
+                mentions::dismiss(client.as_ref(), &mention).await?;
+                let is_allowed =
+                    mentions::is_allowed(&self.config.allowed, &mention.acct, &account);
+                // The subscriber commands are only taken from the direct messages,
+                // the public replies to the bot are not commands.
+                let is_subscriber = has_subscriptions && mention.is_direct;
+                let subscriber = Subscriber {
+                    account: account.name.clone(),
+                    acct: mention.acct.clone(),
+                };
+                tracing::info!("Command from {}: {}", mention.acct, mention.text);
+                let reply = match mentions::SubscriberCommand::parse(&mention.text) {
+                    Ok(
+                        command @ (mentions::SubscriberCommand::Subscribe(_)
+                        | mentions::SubscriberCommand::Unsubscribe(_)),
+                    ) if is_subscriber => self.subscriber_command(command, subscriber).await?,
+                    _ if is_allowed => match mentions::Command::parse(&mention.text) {
+                        Ok(command) => command.apply(&mut self.config)?,
+                        Err(usage) => usage,
+                    },
+                    Ok(command) if is_subscriber => {
+                        self.subscriber_command(command, subscriber).await?
+                    }
+                    Err(usage) if is_subscriber => usage,
+                    _ => {
+                        tracing::info!(
+                            "Ignoring the mention of {}: {}",
+                            mention.acct,
+                            mention.text
+                        );
+                        continue;
+                    }
+                };
+                self.status
+                    .update(&self.config.accounts, &self.config.feeds);
+                if self.config.dry_run {
+                    tracing::info!("Dry run. Not replying to {}: {}", mention.acct, reply);
+                    continue;
+                }
+                mentions::reply(client.as_ref(), &mention, &reply).await?;
             }
         }
         Ok(())
     }
+
+    /// Reloads the feeds file and the config file.
+    /// The posting queues of the changed accounts are replaced, the old queues
+    /// are closed after posting their contents.
+    pub fn reload(&mut self, cli: &RunArgs) -> PResult<()> {
+        self.config.reload(cli)?;
+        // The access tokens may be changed, the commands are tried again.
+        self.no_commands.clear();
+        self.status
+            .update(&self.config.accounts, &self.config.feeds);
+        let shared_config = Arc::new(self.config.clone());
+        let mut old_posters = std::mem::take(&mut self.posters);
+        for account in &self.config.accounts {
+            if let Some(idx) = old_posters.iter().position(|p| &p.account == account) {
+                self.posters.push(old_posters.remove(idx));
+            } else {
+                tracing::info!("Account added or changed: {}", account.name);
+                self.posters.push(Poster::spawn(
+                    account.clone(),
+                    Arc::clone(&shared_config),
+                    self.shutdown.clone(),
+                    self.status.clone(),
+                    self.errors_sender.clone(),
+                    self.notifier.handle(),
+                ));
+            }
+        }
+        self.closing.retain(|closing| !closing.is_finished());
+        for poster in old_posters {
+            self.closing.push(tokio::spawn(poster.close()));
+        }
+        Ok(())
+    }
+
+    /// Waits for a fatal error of the posters.
+    pub async fn fatal_error(&mut self) -> PError {
+        self.errors
+            .recv()
+            .await
+            .expect("The bot keeps a sender, the channel is never closed")
+    }
+
+    /// Waits until the queued contents are posted, and saves the feeds state.
+    /// The feeds of the abandoned contents are rewound, so they are posted on the next run.
+    /// Returns the fatal error of the posters, if any.
+    pub async fn finish(mut self) -> PResult<()> {
+        let mut abandoned = Vec::new();
+        for poster in std::mem::take(&mut self.posters) {
+            abandoned.extend(poster.close().await);
+        }
+        for closing in std::mem::take(&mut self.closing) {
+            abandoned.extend(closing.await.unwrap_or_default());
+        }
+        self.rewind(&abandoned);
+        self.notifier.close().await;
+        self.config.save_state()?;
+        if let Some(subscriptions_file) = &self.config.subscriptions_file {
+            self.subscriptions.write(subscriptions_file)?;
+        }
+        match self.errors.try_recv() {
+            Ok(err) => Err(err),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Moves the `last_post` of the feeds before their oldest abandoned content.
+    /// A content that is posted by the other accounts of the feed is posted again by them.
+    fn rewind(&mut self, abandoned: &[Content]) {
+        for content in abandoned {
+            let Some(date) = content.date else {
+                continue;
+            };
+            let last_post = match &content.recipient {
+                Some(_) => {
+                    // The subscribed feed is restored from its rewound state on the next poll.
+                    self.subscribed_feeds.remove(content.feed_url.as_str());
+                    self.subscriptions
+                        .feeds
+                        .get_mut(content.feed_url.as_str())
+                        .map(|feed| &mut feed.state.last_post)
+                }
+                None => self
+                    .config
+                    .feeds
+                    .iter_mut()
+                    .find(|feed| feed.url == content.feed_url)
+                    .map(|feed| &mut feed.last_post),
+            };
+            if let Some(last_post) = last_post {
+                let rewound = date.saturating_sub(1);
+                if last_post.is_none_or(|last_post| last_post > rewound) {
+                    tracing::info!(
+                        "The feed {} is rewound to post `{}` on the next run.",
+                        content.feed_url,
+                        content.title
+                    );
+                    *last_post = Some(rewound);
+                }
+            }
+        }
+    }
 }
 
-/// Runs the bot. Infinite loop.
-pub async fn run(cli: Cli) -> PResult<()> {
+/// Checks the feeds one time, and waits until the new contents are posted.
+pub async fn once(cli: RunArgs) -> PResult<()> {
     let config = Config::new(&cli)?;
-    let mut bot = Bot::new(config)?;
-    loop {
-        // If the error is a request error, print it and continue.
-        // Otherwise, return the error.
-        match bot.post_new_contents().await {
-            Ok(_) => log::info!("Finished checking for new contents."),
-            Err(PError::Request(err)) => {
-                log::error!("Error: {}", err);
-                eprintln!("Error: {}", err)
-            }
-            Err(PError::Megalodon(err)) => {
-                log::error!("Error: {}", err);
+    let mut shutdown = Shutdown::listen()?;
+    let mut bot = Bot::new(config, shutdown.clone())?;
+    let result = tokio::select! {
+        result = bot.poll() => result,
+        _ = shutdown.wait() => Ok(()),
+    };
+    bot.finish().await?;
+    result
+}
+
+/// Runs the bot. Loops until the bot receives `SIGINT` or `SIGTERM`, then
+/// waits for the current items to be posted and saves the feeds state.
+pub async fn run(cli: RunArgs) -> PResult<()> {
+    let config = Config::new(&cli)?;
+    let mut shutdown = Shutdown::listen()?;
+    let mut bot = Bot::new(config, shutdown.clone())?;
+    let mut watcher = Watcher::new(&cli)?;
+    if let Some(addr) = cli.listen {
+        server::spawn(addr, bot.status.clone(), shutdown.clone())?;
+    }
+    let result = loop {
+        // Only the fatal errors stop the bot, the other errors are reported
+        // and the feeds are checked again in the next poll.
+        let result = tokio::select! {
+            result = bot.poll() => result,
+            _ = shutdown.wait() => break Ok(()),
+        };
+        match result {
+            Ok(_) => tracing::info!("Finished checking for new contents."),
+            Err(err) if err.is_fatal() => break Err(err),
+            Err(err) => {
+                tracing::error!("Error: {}", err);
                 eprintln!("Error: {}", err)
             }
-            Err(err) => return Err(err),
         }
         // Sleep before checking for new contents.
-        log::info!(
+        tracing::info!(
             "Waiting for new contents. Sleeping for {} seconds.",
             bot.config.watting_new
         );
-        tokio::time::sleep(std::time::Duration::from_secs(bot.config.watting_new)).await;
+        bot.status.set_next_poll(
+            chrono::Utc::now() + chrono::Duration::seconds(bot.config.watting_new as i64),
+        );
+        let reload = tokio::select! {
+            reload = watcher.wait(std::time::Duration::from_secs(bot.config.watting_new)) => reload,
+            err = bot.fatal_error() => break Err(err),
+            _ = shutdown.wait() => break Ok(()),
+        };
+        if reload {
+            // Keep the old config if the new one is invalid.
+            if let Err(err) = bot.reload(&cli) {
+                tracing::error!("Error reloading the config: {}", err);
+                eprintln!("Error reloading the config: {}", err)
+            }
+        }
+    };
+    if let Err(err) = &result {
+        bot.notifier.notify(format!("The bot is stopped: {err}"));
+    }
+    bot.finish().await?;
+    result?;
+    println!("The bot is stopped.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use clap::Parser;
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server,
+    };
+
+    use super::*;
+    use crate::cli::{Cli, Command};
+
+    /// The account of the test instance.
+    const ACCOUNT: &str = r#"{"id":"1","username":"bot","acct":"bot","display_name":"bot",
+        "locked":false,"created_at":"2023-01-01T00:00:00Z","followers_count":0,
+        "following_count":0,"statuses_count":0,"note":"","url":"https://example.com/@bot",
+        "avatar":"","avatar_static":"","header":"","header_static":"","emojis":[]}"#;
+
+    /// Serves an instance that rejects every request with `403`, like an access token
+    /// without the notifications scopes. If `valid_token` is `true`, the credentials of the
+    /// account are returned. Returns its url and the number of notifications requests.
+    fn forbidding_instance(valid_token: bool) -> (String, Arc<AtomicUsize>) {
+        let notifications = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&notifications);
+        let make_service = make_service_fn(move |_| {
+            let counter = Arc::clone(&counter);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    if req.uri().path() == "/api/v1/notifications" {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                    }
+                    let response = if valid_token
+                        && req.uri().path() == "/api/v1/accounts/verify_credentials"
+                    {
+                        Response::new(Body::from(ACCOUNT))
+                    } else {
+                        Response::builder()
+                            .status(403)
+                            .body(Body::from(
+                                r#"{"error":"This action is outside the authorized scopes"}"#,
+                            ))
+                            .unwrap()
+                    };
+                    async { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        (url, notifications)
+    }
+
+    /// Creates a bot of the instance with the extra arguments.
+    fn bot(name: &str, instance: &str, args: &[&str]) -> Bot {
+        let feeds_file = std::env::temp_dir().join(format!(
+            "pleroma-rss-test-{}-{name}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&feeds_file, "https://example.com/feed.xml\n").unwrap();
+        let cli = Cli::try_parse_from(
+            [
+                "pleroma-rss",
+                "run",
+                "-m",
+                "-a",
+                "token",
+                "-b",
+                instance,
+                "-f",
+            ]
+            .into_iter()
+            .chain([feeds_file.to_str().unwrap()])
+            // The preview image needs a template, the item images are attached instead.
+            .chain(cfg!(feature = "preview-image").then_some("--attach-images"))
+            .chain(args.iter().copied()),
+        )
+        .unwrap();
+        let Command::Run(args) = cli.command else {
+            unreachable!()
+        };
+        let config = Config::new(&args).unwrap();
+        std::fs::remove_file(feeds_file).unwrap();
+        Bot::new(config, Shutdown::listen().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn admin_does_not_read_notifications() {
+        let (instance, notifications) = forbidding_instance(true);
+        let mut bot = bot("admin", &instance, &["--admin", "admin@example.com"]);
+        bot.handle_mentions().await.unwrap();
+        assert_eq!(notifications.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn forbidden_notifications_disable_commands() {
+        let (instance, notifications) = forbidding_instance(true);
+        let mut bot = bot(
+            "allow",
+            &instance,
+            &[
+                "--admin",
+                "admin@example.com",
+                "--allow",
+                "admin@example.com",
+            ],
+        );
+        bot.handle_mentions().await.unwrap();
+        assert_eq!(bot.no_commands, ["default"]);
+        // The commands are not tried again until the config is reloaded.
+        bot.handle_mentions().await.unwrap();
+        assert_eq!(notifications.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn rejected_token_stops_the_bot() {
+        let (instance, _) = forbidding_instance(false);
+        let mut bot = bot("token", &instance, &["--allow", "admin@example.com"]);
+        let err = bot.handle_mentions().await.unwrap_err();
+        assert!(matches!(err, PError::Unauthorized(..)), "{err}");
+        assert!(err.is_fatal());
+    }
+
+    /// Returns an item of the feed with the publish date.
+    fn content(feed_url: &str, date: Option<u64>, recipient: Option<&str>) -> Content {
+        let mut content = Content::new(
+            feed_url.parse().unwrap(),
+            "Title",
+            "https://example.com/item",
+            None,
+            "Description",
+            #[cfg(feature = "preview-image")]
+            Vec::new(),
+            #[cfg(feature = "preview-image")]
+            None,
+        );
+        content.date = date;
+        content.recipient = recipient.map(ToOwned::to_owned);
+        content
+    }
+
+    #[tokio::test]
+    async fn rewind_to_the_oldest_abandoned_item() {
+        let (instance, _) = forbidding_instance(true);
+        let mut bot = bot("rewind", &instance, &[]);
+        let feed_url = "https://example.com/feed.xml";
+        let subscribed_url = "https://example.org/feed.xml";
+        bot.config.feeds[0].last_post = Some(100);
+        let subscriber = Subscriber {
+            account: "default".to_owned(),
+            acct: "user@example.com".to_owned(),
+        };
+        let state = FeedState {
+            last_post: Some(100),
+            ..Default::default()
+        };
+        bot.subscriptions
+            .subscribe(subscribed_url, subscriber, state);
+        bot.rewind(&[
+            content(feed_url, Some(80), None),
+            content(feed_url, Some(50), None),
+            content(feed_url, None, None),
+            content(subscribed_url, Some(90), Some("user@example.com")),
+        ]);
+        assert_eq!(bot.config.feeds[0].last_post, Some(49));
+        assert_eq!(
+            bot.subscriptions.feeds[subscribed_url].state.last_post,
+            Some(89)
+        );
+        // A newer item does not move the feeds forward.
+        bot.rewind(&[content(feed_url, Some(200), None)]);
+        assert_eq!(bot.config.feeds[0].last_post, Some(49));
     }
 }
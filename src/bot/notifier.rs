@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use megalodon::{entities::StatusVisibility, megalodon::PostStatusInputOptions};
+use tokio::{sync::mpsc, task::JoinHandle, time::Instant};
+
+use super::status::Status;
+
+/// The maximum number of problems in one message, the rest are counted.
+const MAX_PROBLEMS: usize = 10;
+/// The time to wait for the last message when the bot stops.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends the problems of the bot to the admin account as direct messages.
+/// The problems are rate limited, at most one message is sent every interval,
+/// and the problems found in the meantime are summarized in the next message.
+#[derive(Debug)]
+pub struct Notifier {
+    sender: NotifierHandle,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Notifier {
+    /// Spawns the notifier of the admin account, e.g. `admin@example.com`.
+    /// The messages are sent from the first bot account that can post.
+    /// Without an admin, the problems are only printed.
+    pub fn spawn(admin: Option<String>, interval: Duration, dry_run: bool, status: Status) -> Self {
+        let Some(admin) = admin else {
+            return Self {
+                sender: NotifierHandle(None),
+                handle: None,
+            };
+        };
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let admin = admin.trim_start_matches('@').to_owned();
+        let handle = tokio::spawn(run(admin, interval, dry_run, status, receiver));
+        Self {
+            sender: NotifierHandle(Some(sender)),
+            handle: Some(handle),
+        }
+    }
+
+    /// Reports a problem to the admin.
+    pub fn notify(&self, problem: impl Into<String>) {
+        self.sender.notify(problem)
+    }
+
+    /// Returns a handle to report the problems from other tasks.
+    pub fn handle(&self) -> NotifierHandle {
+        self.sender.clone()
+    }
+
+    /// Stops the notifier, waits a short time for the pending problems to be sent.
+    /// The handles should be dropped before, otherwise the notifier is abandoned.
+    pub async fn close(self) {
+        drop(self.sender);
+        if let Some(handle) = self.handle {
+            if tokio::time::timeout(CLOSE_TIMEOUT, handle).await.is_err() {
+                tracing::warn!("The pending admin notifications are abandoned.");
+            }
+        }
+    }
+}
+
+/// A handle to report problems to the admin, see [`Notifier::notify`].
+#[derive(Debug, Clone)]
+pub struct NotifierHandle(Option<mpsc::UnboundedSender<String>>);
+
+impl NotifierHandle {
+    /// Reports a problem to the admin.
+    pub fn notify(&self, problem: impl Into<String>) {
+        let problem = problem.into();
+        eprintln!("{problem}");
+        if let Some(sender) = &self.0 {
+            let _ = sender.send(problem);
+        }
+    }
+}
+
+/// Sends the reported problems until the notifier is closed.
+async fn run(
+    admin: String,
+    interval: Duration,
+    dry_run: bool,
+    status: Status,
+    mut receiver: mpsc::UnboundedReceiver<String>,
+) {
+    let mut last_sent: Option<Instant> = None;
+    while let Some(problem) = receiver.recv().await {
+        let mut problems = vec![problem];
+        if let Some(last_sent) = last_sent {
+            // The problems reported while waiting are sent in the same message.
+            // The message is sent immediately when the notifier is closed.
+            let sleep = tokio::time::sleep_until(last_sent + interval);
+            tokio::pin!(sleep);
+            loop {
+                tokio::select! {
+                    _ = &mut sleep => break,
+                    problem = receiver.recv() => match problem {
+                        Some(problem) => problems.push(problem),
+                        None => break,
+                    },
+                }
+            }
+        }
+        while let Ok(problem) = receiver.try_recv() {
+            problems.push(problem);
+        }
+        let message = summary(&admin, &problems);
+        last_sent = Some(Instant::now());
+        if dry_run {
+            tracing::info!("Dry run. Not notifying the admin: {}", message);
+            continue;
+        }
+        send(&message, &status).await;
+    }
+}
+
+/// Returns the message of the problems.
+fn summary(admin: &str, problems: &[String]) -> String {
+    let mut message = format!("@{admin} pleroma-rss found {} problem(s):", problems.len());
+    for problem in problems.iter().take(MAX_PROBLEMS) {
+        message.push_str("\n- ");
+        message.push_str(problem);
+    }
+    if problems.len() > MAX_PROBLEMS {
+        message.push_str(&format!("\n- And {} more.", problems.len() - MAX_PROBLEMS));
+    }
+    message
+}
+
+/// Sends the message from the first account that can post.
+async fn send(message: &str, status: &Status) {
+    let options = PostStatusInputOptions {
+        visibility: Some(StatusVisibility::Direct),
+        ..Default::default()
+    };
+    for account in status.accounts() {
+        match account
+            .client()
+            .post_status(message.to_owned(), Some(&options))
+            .await
+        {
+            Ok(_) => {
+                tracing::info!("The admin is notified by the account: {}", account.name);
+                return;
+            }
+            Err(err) => tracing::error!(
+                "Error notifying the admin by the account {}: {}",
+                account.name,
+                err
+            ),
+        }
+    }
+    tracing::error!("The admin is not notified, no account can post.");
+}
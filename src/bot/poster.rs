@@ -0,0 +1,201 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use super::{notifier::NotifierHandle, rss::Content, shutdown::Shutdown, status::Status};
+use crate::{
+    config::{Account, Config},
+    errors::{Error as PError, ErrorClass},
+    metrics,
+};
+
+/// The number of times that an item is posted before it is dropped,
+/// if the instance or the network fails.
+const MAX_POST_ATTEMPTS: u32 = 5;
+/// The delay before posting an item again, it is doubled after every attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// The posting queue of an account.
+/// Every account has its own client and queue, the items are posted one by one
+/// with the account sleep time between them.
+#[derive(Debug)]
+pub struct Poster {
+    /// The account of the queue.
+    pub account: Account,
+    sender: mpsc::UnboundedSender<Content>,
+    handle: JoinHandle<Vec<Content>>,
+}
+
+impl Poster {
+    /// Spawns the posting task of the account.
+    /// The task finishes the current item and stops when the shutdown is triggered.
+    /// A fatal error stops the task, and is sent to `errors`.
+    pub fn spawn(
+        account: Account,
+        config: Arc<Config>,
+        shutdown: Shutdown,
+        status: Status,
+        errors: mpsc::UnboundedSender<PError>,
+        notifier: NotifierHandle,
+    ) -> Self {
+        tracing::debug!("Spawning the poster of the account: {}", account.name);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(run(
+            account.clone(),
+            config,
+            receiver,
+            shutdown,
+            status,
+            errors,
+            notifier,
+        ));
+        Self {
+            account,
+            sender,
+            handle,
+        }
+    }
+
+    /// Adds the content to the posting queue.
+    pub fn push(&self, content: Content) {
+        tracing::debug!(
+            "Adding `{}` to the queue of the account: {}",
+            content.title,
+            self.account.name
+        );
+        let account = self.account.name.as_str();
+        if self.sender.send(content).is_ok() {
+            metrics::QUEUE_DEPTH.with_label_values(&[account]).inc();
+        } else {
+            tracing::error!(
+                "The poster of the account {} is stopped.",
+                self.account.name
+            );
+        }
+    }
+
+    /// Closes the queue, and waits until the queued contents are posted.
+    /// Returns the contents that are not posted because of the shutdown or a fatal error.
+    pub async fn close(self) -> Vec<Content> {
+        tracing::debug!("Closing the queue of the account: {}", self.account.name);
+        drop(self.sender);
+        self.handle.await.unwrap_or_else(|err| {
+            tracing::error!(
+                "The poster of the account {} panicked: {}",
+                self.account.name,
+                err
+            );
+            Vec::new()
+        })
+    }
+}
+
+/// Posts the queued contents until the queue is closed or the shutdown is triggered.
+/// An item that fails because of the instance or the network is posted again after a delay,
+/// up to [`MAX_POST_ATTEMPTS`] times.
+/// Returns the queued contents that are abandoned, with the item that was being retried.
+async fn run(
+    account: Account,
+    config: Arc<Config>,
+    mut receiver: mpsc::UnboundedReceiver<Content>,
+    mut shutdown: Shutdown,
+    status: Status,
+    errors: mpsc::UnboundedSender<PError>,
+    notifier: NotifierHandle,
+) -> Vec<Content> {
+    let client = account.client();
+    let items_sleep = account.items_sleep.unwrap_or_default();
+    // The failed item and its number of attempts.
+    let mut retry: Option<(Content, u32)> = None;
+    loop {
+        let (content, attempts) = match retry.take() {
+            Some(retry) => retry,
+            None => {
+                let content = tokio::select! {
+                    biased;
+                    _ = shutdown.wait() => break,
+                    content = receiver.recv() => match content {
+                        Some(content) => content,
+                        None => return Vec::new(),
+                    },
+                };
+                metrics::QUEUE_DEPTH
+                    .with_label_values(&[&account.name])
+                    .dec();
+                (content, 1)
+            }
+        };
+        let mut sleep = Duration::from_secs(items_sleep);
+        match content.post(client.as_ref(), &account.name, &config).await {
+            Ok(()) => {
+                metrics::ITEMS
+                    .with_label_values(&[content.feed_url.as_str(), "posted"])
+                    .inc();
+                status.item_posted(&content, &account.name);
+            }
+            Err(err) => {
+                metrics::ITEMS
+                    .with_label_values(&[content.feed_url.as_str(), "failed"])
+                    .inc();
+                tracing::error!("Error: {} (account: {})", err, account.name);
+                // A rejected item may be a rejected access token, it is checked.
+                let err = match err.instance_status() {
+                    Some(401 | 403) => match account.verify_token(client.as_ref()).await {
+                        Err(token_err) if token_err.is_fatal() => token_err,
+                        _ => err,
+                    },
+                    _ => err,
+                };
+                if err.is_fatal() {
+                    notifier.notify(err.to_string());
+                    let _ = errors.send(err);
+                    // The item is abandoned, it is posted on the next run.
+                    retry = Some((content, attempts));
+                    break;
+                }
+                if err.class() == ErrorClass::Transient && attempts < MAX_POST_ATTEMPTS {
+                    sleep = sleep.max(RETRY_DELAY * 2_u32.pow(attempts - 1));
+                    tracing::warn!(
+                        "Posting `{}` again after {} seconds (attempt {} of {}).",
+                        content.title,
+                        sleep.as_secs(),
+                        attempts + 1,
+                        MAX_POST_ATTEMPTS
+                    );
+                    retry = Some((content, attempts + 1));
+                } else {
+                    // The item is dropped, it will not be retried.
+                    notifier.notify(format!(
+                        "The item {} of the feed {} is not posted by the account {}: {}",
+                        content.link, content.feed_url, account.name, err
+                    ));
+                }
+            }
+        }
+        tracing::info!(
+            "Sleeping for {} seconds, before sending the next item to the account: {}",
+            sleep.as_secs(),
+            account.name
+        );
+        tokio::select! {
+            _ = shutdown.wait() => break,
+            _ = tokio::time::sleep(sleep) => {}
+        }
+    }
+    receiver.close();
+    let mut abandoned = Vec::from_iter(retry.map(|(content, _)| content));
+    while let Ok(content) = receiver.try_recv() {
+        metrics::QUEUE_DEPTH
+            .with_label_values(&[&account.name])
+            .dec();
+        abandoned.push(content);
+    }
+    if !abandoned.is_empty() {
+        tracing::warn!(
+            "Abandoned {} queued item(s) of the account: {}",
+            abandoned.len(),
+            account.name
+        );
+    }
+    abandoned
+}
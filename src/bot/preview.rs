@@ -0,0 +1,307 @@
+use std::path::{Path, PathBuf};
+
+use ::image::{imageops::FilterType, DynamicImage, Rgba, RgbaImage};
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use serde::Deserialize;
+
+use crate::errors::{Error as PError, Result as PResult};
+
+/// The layout of the natively rendered preview image, read from a TOML file.
+/// The image, the title, the description and the domain are drawn in this order,
+/// the missing boxes are not drawn.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    /// The width of the preview in pixels.
+    pub width: u32,
+    /// The height of the preview in pixels.
+    pub height: u32,
+    /// The background color.
+    #[serde(default = "Color::black")]
+    pub background: Color,
+    /// The font files. A character that is missing in a font is drawn with the next one.
+    /// The relative paths are relative to the layout file.
+    pub fonts: Vec<PathBuf>,
+    /// The box of the item image, the image is cropped to fill it.
+    pub image: Option<ImageBox>,
+    /// The box of the item title.
+    pub title: Option<TextBox>,
+    /// The box of the item description.
+    pub description: Option<TextBox>,
+    /// The box of the item site domain, e.g. `example.com`.
+    pub domain: Option<TextBox>,
+}
+
+/// A box of the item image.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageBox {
+    /// The left edge of the box.
+    #[serde(default)]
+    pub x: i64,
+    /// The top edge of the box.
+    #[serde(default)]
+    pub y: i64,
+    /// The width of the box.
+    pub width: u32,
+    /// The height of the box.
+    pub height: u32,
+    /// The opacity of the image, from 0 to 1.
+    #[serde(default = "full_opacity")]
+    pub opacity: f32,
+}
+
+/// A box of text, the text is wrapped to its width.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextBox {
+    /// The left edge of the box.
+    #[serde(default)]
+    pub x: i64,
+    /// The top edge of the box.
+    #[serde(default)]
+    pub y: i64,
+    /// The width of the box, the longer lines are wrapped.
+    pub width: u32,
+    /// The font size in pixels.
+    pub size: f32,
+    /// The text color.
+    #[serde(default = "Color::white")]
+    pub color: Color,
+    /// The maximum number of lines, the rest of the text is replaced with `…`.
+    #[serde(default = "default_max_lines")]
+    pub max_lines: usize,
+    /// The line height, relative to the font size.
+    #[serde(default = "default_line_height")]
+    pub line_height: f32,
+}
+
+/// A color, written as `#rrggbb` or `#rrggbbaa`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color(Rgba<u8>);
+
+/// The content of a preview image.
+#[derive(Debug)]
+pub struct Card<'a> {
+    /// The item title.
+    pub title: &'a str,
+    /// The item description.
+    pub description: &'a str,
+    /// The item site domain.
+    pub domain: &'a str,
+    /// The encoded item image, e.g. a PNG or a JPEG file.
+    pub image: Option<&'a [u8]>,
+}
+
+fn full_opacity() -> f32 {
+    1.0
+}
+
+fn default_max_lines() -> usize {
+    2
+}
+
+fn default_line_height() -> f32 {
+    1.2
+}
+
+impl Color {
+    fn black() -> Self {
+        Self(Rgba([0, 0, 0, 255]))
+    }
+
+    fn white() -> Self {
+        Self(Rgba([255, 255, 255, 255]))
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(color: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid color `{color}`, expected `#rrggbb` or `#rrggbbaa`");
+        let hex = color.strip_prefix('#').ok_or_else(invalid)?;
+        if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut rgba = [255; 4];
+        for (idx, channel) in rgba.iter_mut().enumerate().take(hex.len() / 2) {
+            *channel = u8::from_str_radix(&hex[idx * 2..idx * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(Rgba(rgba)))
+    }
+}
+
+impl Layout {
+    /// Reads the layout file.
+    pub fn read(path: &Path) -> PResult<Self> {
+        tracing::debug!("Reading the preview layout: {}", path.display());
+        let mut layout: Self = toml::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|err| PError::Preview(format!("Invalid layout: {err}")))?;
+        if layout.fonts.is_empty() {
+            return Err(PError::Preview("The layout has no fonts".to_owned()));
+        }
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for font in &mut layout.fonts {
+            *font = dir.join(&font);
+        }
+        Ok(layout)
+    }
+
+    /// Renders the preview image of the card.
+    pub fn render(&self, card: &Card) -> PResult<RgbaImage> {
+        let fonts = self
+            .fonts
+            .iter()
+            .map(|path| {
+                FontVec::try_from_vec(std::fs::read(path)?).map_err(|_| {
+                    PError::Preview(format!("The font `{}` is invalid", path.display()))
+                })
+            })
+            .collect::<PResult<Vec<_>>>()?;
+        let mut canvas = RgbaImage::from_pixel(self.width, self.height, self.background.0);
+        if let (Some(image_box), Some(image)) = (&self.image, card.image) {
+            match ::image::load_from_memory(image) {
+                Ok(image) => draw_image(&mut canvas, image_box, image),
+                Err(err) => tracing::warn!("The item image is not drawn: {}", err),
+            }
+        }
+        for (text_box, text) in [
+            (&self.title, card.title),
+            (&self.description, card.description),
+            (&self.domain, card.domain),
+        ] {
+            if let Some(text_box) = text_box {
+                draw_text(&mut canvas, &fonts, text_box, text);
+            }
+        }
+        Ok(canvas)
+    }
+}
+
+/// Draws the image cropped to fill the box.
+fn draw_image(canvas: &mut RgbaImage, image_box: &ImageBox, image: DynamicImage) {
+    let mut image = image
+        .resize_to_fill(image_box.width, image_box.height, FilterType::Triangle)
+        .into_rgba8();
+    let opacity = image_box.opacity.clamp(0.0, 1.0);
+    for pixel in image.pixels_mut() {
+        pixel[3] = (f32::from(pixel[3]) * opacity).round() as u8;
+    }
+    ::image::imageops::overlay(canvas, &image, image_box.x, image_box.y);
+}
+
+/// Draws the text wrapped to the box.
+fn draw_text(canvas: &mut RgbaImage, fonts: &[FontVec], text_box: &TextBox, text: &str) {
+    let scale = PxScale::from(text_box.size);
+    let ascent = fonts[0].as_scaled(scale).ascent();
+    let line_height = text_box.size * text_box.line_height;
+    let lines = wrap(
+        fonts,
+        scale,
+        text,
+        text_box.width as f32,
+        text_box.max_lines,
+    );
+    for (idx, line) in lines.iter().enumerate() {
+        let baseline = text_box.y as f32 + idx as f32 * line_height + ascent;
+        let mut x = text_box.x as f32;
+        for c in line.chars() {
+            let font = font_of(fonts, c);
+            let scaled = font.as_scaled(scale);
+            let glyph = scaled.scaled_glyph(c);
+            let advance = scaled.h_advance(glyph.id);
+            let glyph = glyph
+                .id
+                .with_scale_and_position(scale, ab_glyph::point(x, baseline));
+            x += advance;
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i64 + i64::from(gx);
+                let py = bounds.min.y as i64 + i64::from(gy);
+                if (0..i64::from(canvas.width())).contains(&px)
+                    && (0..i64::from(canvas.height())).contains(&py)
+                {
+                    blend(
+                        canvas.get_pixel_mut(px as u32, py as u32),
+                        text_box.color.0,
+                        coverage,
+                    );
+                }
+            });
+        }
+    }
+}
+
+/// Returns the first font that has the character.
+fn font_of(fonts: &[FontVec], c: char) -> &FontVec {
+    fonts
+        .iter()
+        .find(|font| font.glyph_id(c).0 != 0)
+        .unwrap_or(&fonts[0])
+}
+
+/// Returns the width of the text in pixels.
+fn text_width(fonts: &[FontVec], scale: PxScale, text: &str) -> f32 {
+    text.chars()
+        .map(|c| {
+            let font = font_of(fonts, c).as_scaled(scale);
+            font.h_advance(font.glyph_id(c))
+        })
+        .sum()
+}
+
+/// Wraps the text to the width, at most `max_lines` lines.
+/// The lines that do not fit, and the last line of a truncated text, end with `…`.
+fn wrap(
+    fonts: &[FontVec],
+    scale: PxScale,
+    text: &str,
+    width: f32,
+    max_lines: usize,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_owned()
+        } else {
+            format!("{line} {word}")
+        };
+        if line.is_empty() || text_width(fonts, scale, &candidate) <= width {
+            line = candidate;
+        } else {
+            lines.push(std::mem::replace(&mut line, word.to_owned()));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    let is_truncated = lines.len() > max_lines;
+    lines.truncate(max_lines);
+    let last = lines.len().saturating_sub(1);
+    for (idx, line) in lines.iter_mut().enumerate() {
+        if (is_truncated && idx == last) || text_width(fonts, scale, line) > width {
+            while !line.is_empty() && text_width(fonts, scale, &format!("{line}…")) > width {
+                line.pop();
+            }
+            *line = format!("{}…", line.trim_end());
+        }
+    }
+    lines
+}
+
+/// Blends the color into the pixel, by the glyph coverage.
+fn blend(pixel: &mut Rgba<u8>, color: Rgba<u8>, coverage: f32) {
+    let alpha = coverage.clamp(0.0, 1.0) * f32::from(color[3]) / 255.0;
+    for channel in 0..3 {
+        pixel[channel] = (f32::from(pixel[channel]) * (1.0 - alpha)
+            + f32::from(color[channel]) * alpha)
+            .round() as u8;
+    }
+    pixel[3] = (f32::from(pixel[3]) + (255.0 - f32::from(pixel[3])) * alpha).round() as u8;
+}
@@ -0,0 +1,173 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use hyper::client::connect::dns::Name;
+use once_cell::sync::Lazy;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    redirect::Policy,
+};
+use url::{Host, Url};
+
+/// The maximum time of a request to a user url.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// The maximum number of redirects of a user url.
+#[cfg(feature = "preview-image")]
+const MAX_REDIRECTS: usize = 5;
+
+/// The client of the user feeds, it only connects to the public addresses.
+/// The redirects are followed by [`Feed::fetch`](super::Feed), which checks them with [`is_public_url`].
+pub(super) static FEEDS_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| client(Policy::none()));
+/// The client of the pages and images of the user feeds, it only connects to the public
+/// addresses and only follows the redirects to the public urls.
+#[cfg(feature = "preview-image")]
+pub(super) static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    client(Policy::custom(|attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if !is_public_url(attempt.url()) {
+            attempt.error("the redirect is not to a public url")
+        } else {
+            attempt.follow()
+        }
+    }))
+});
+
+/// Creates a client that only resolves the hosts to their public addresses.
+fn client(redirect: Policy) -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(redirect)
+        .timeout(TIMEOUT)
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+        .expect("The client options are valid")
+}
+
+/// Resolves the hosts to their public addresses, a host without one is not resolved.
+/// Resolving on every connection, instead of checking the url before the request,
+/// keeps a host from changing its address between the check and the request.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                return Err(format!("The host {} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Returns `true` if the url can be requested for a user, it is an `http` or `https` url
+/// and its host is not a private address. The domains are checked when they are resolved.
+pub fn is_public_url(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+        && match url.host() {
+            Some(Host::Domain(_)) => true,
+            Some(Host::Ipv4(ip)) => is_public(ip.into()),
+            Some(Host::Ipv6(ip)) => is_public(ip.into()),
+            None => false,
+        }
+}
+
+/// Returns `true` if the url is public and its host resolves to a public address.
+pub async fn resolves_to_public(url: &Url) -> bool {
+    if !is_public_url(url) {
+        return false;
+    }
+    let Some(Host::Domain(domain)) = url.host() else {
+        return true;
+    };
+    tokio::net::lookup_host((domain, 0))
+        .await
+        .is_ok_and(|mut addrs| addrs.any(|addr| is_public(addr.ip())))
+}
+
+/// Returns `true` if the address is reachable on the internet, it is not a loopback,
+/// private, link-local (e.g. the cloud metadata), shared, multicast or reserved address.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // The shared address space `100.64.0.0/10`.
+                || (first == 100 && second & 0xc0 == 64)
+                // The `0.0.0.0/8` and `240.0.0.0/4` reserved addresses.
+                || first == 0
+                || first >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // The unique local addresses `fc00::/7`.
+                    || first & 0xfe00 == 0xfc00
+                    // The link-local addresses `fe80::/10`.
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn private_urls() {
+        for private in [
+            "http://127.0.0.1:8080/status",
+            "http://10.0.0.1/feed.xml",
+            "http://172.16.0.1/feed.xml",
+            "http://192.168.1.1/feed.xml",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://100.64.0.1/feed.xml",
+            "http://0.0.0.0/feed.xml",
+            "http://[::1]/feed.xml",
+            "http://[fd00::1]/feed.xml",
+            "http://[fe80::1]/feed.xml",
+            "http://[::ffff:127.0.0.1]/feed.xml",
+            "file:///etc/passwd",
+            "ftp://example.com/feed.xml",
+        ] {
+            assert!(!is_public_url(&url(private)), "{private}");
+        }
+    }
+
+    #[test]
+    fn public_urls() {
+        for public in [
+            "https://example.com/feed.xml",
+            "http://93.184.216.34/feed.xml",
+            "http://[2606:2800:220:1:248:1893:25c8:1946]/feed.xml",
+        ] {
+            assert!(is_public_url(&url(public)), "{public}");
+        }
+    }
+
+    #[tokio::test]
+    async fn localhost_is_not_public() {
+        assert!(!resolves_to_public(&url("http://localhost:8080/status")).await);
+    }
+}
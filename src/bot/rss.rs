@@ -1,155 +1,413 @@
+#[cfg(feature = "native-preview")]
+use super::preview;
+use super::{
+    full_text,
+    link_preview::{self, LinkPreviewOptions, PageMeta},
+    public,
+};
+#[cfg(feature = "preview-image")]
+use super::{image::get_image_id, image_cache, images};
 #[cfg(feature = "preview-image")]
-use super::image::get_image_id;
+use crate::cli::ImageSource;
 use crate::{
     config::Config,
-    errors::{Error as PError, Result as PResult},
-    utils::remove_html_tags,
+    errors::{Error as PError, ErrorClass, Result as PResult},
+    metrics,
+    utils::{encode_list, remove_html_tags},
 };
-use chrono::DateTime;
-use megalodon::megalodon::PostStatusInputOptions;
+use chrono::{DateTime, Utc};
+use megalodon::{entities::StatusVisibility, megalodon::PostStatusInputOptions, Megalodon};
+use once_cell::sync::Lazy;
+use reqwest::{header::LOCATION, redirect::Policy, StatusCode};
 #[cfg(feature = "preview-image")]
 use std::fs;
 #[cfg(feature = "preview-image")]
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use url::Url;
 
+/// The maximum number of redirects when fetching a feed.
+const MAX_REDIRECTS: usize = 5;
+/// The maximum delay between the retries of a failing feed, in seconds.
+const MAX_BACKOFF: u64 = 24 * 60 * 60;
+/// The maximum time to connect to a feed server.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// The maximum time of a feed request, a stalled server does not block the poll.
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The feeds client, the redirects are followed by [`Feed::fetch`] to detect the moved feeds.
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .redirect(Policy::none())
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .expect("The client config is valid")
+});
+
 /// The RSS feed struct. It contains the feed url and the last post date.
 /// This is used to check if a new post has been made.
 #[derive(Debug, Clone)]
 pub struct Feed {
-    /// The feed url.
+    /// The feed url, as it is in the feeds file.
     pub url: Url,
+    /// The url that the feed is moved to permanently, it is fetched instead of `url`.
+    /// It is kept in the state file, the feeds file is not changed.
+    pub moved_to: Option<Url>,
     /// The last post date.
     pub last_post: Option<u64>,
+    /// The accounts that the feed items will be posted to.
+    /// Empty means all the accounts.
+    pub accounts: Vec<String>,
+    /// The feed title, from the OPML file.
+    pub title: Option<String>,
+    /// The feed tags, from the OPML categories.
+    pub tags: Vec<String>,
+    /// The number of consecutive failures of the feed itself, e.g. a `404` or an invalid feed.
+    pub failures: u32,
+    /// The number of consecutive transient failures, e.g. a network error.
+    /// They delay the next check, but do not disable the feed.
+    pub outages: u32,
+    /// The feed is disabled after too many failures, it is not checked anymore.
+    pub disabled: bool,
+    /// The failing feed is not checked before this time.
+    pub retry_at: Option<DateTime<Utc>>,
+    /// The maximum size of the full text of the items in bytes. With it, the description
+    /// of the items is the main content of their article page.
+    pub full_text: Option<usize>,
+    /// The feed is given by a user, it is only fetched from the public addresses
+    /// and the pages of its items are not fetched.
+    pub public_only: bool,
 }
 
 /// The content of a feed.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Content {
-    /// The uuid of the image. This is used in files names.
-    #[cfg(feature = "preview-image")]
-    pub uuid: String,
+    /// The url of the feed that the content is from.
+    pub feed_url: Url,
     /// The title of the feed.
     pub title: String,
     /// The link of the feed.
     pub link: String,
-    /// The image url of the feed.
+    /// The guid of the item, or its link if it does not have a guid.
+    pub guid: String,
+    /// The images of the item in the feed, with their sources.
+    #[cfg(feature = "preview-image")]
+    pub images: Vec<(ImageSource, String)>,
+    /// The `media:description` of the item media, the alt text of the attached media images.
     #[cfg(feature = "preview-image")]
-    pub image_url: Option<String>,
+    pub image_description: Option<String>,
+    /// The author of the item, or the `article:author` of its page.
+    #[cfg(feature = "preview-image")]
+    pub author: Option<String>,
     /// The description of the feed.
     pub description: String,
+    /// The subscriber that the content is sent to as a direct message.
+    /// Without it, the content is posted publicly.
+    pub recipient: Option<String>,
+    /// The publish date of the item, the `last_post` of the feed once it is posted.
+    pub date: Option<u64>,
 }
 
 impl Content {
     /// Creates a new content instance.
     pub fn new(
+        feed_url: Url,
         title: impl AsRef<str>,
         link: impl AsRef<str>,
+        guid: Option<&str>,
         description: impl AsRef<str>,
-        #[cfg(feature = "preview-image")] image: Option<String>,
+        #[cfg(feature = "preview-image")] images: Vec<(ImageSource, String)>,
+        #[cfg(feature = "preview-image")] image_description: Option<String>,
     ) -> Self {
-        log::debug!("Creating new content: {}", title.as_ref());
+        tracing::debug!("Creating new content: {}", title.as_ref());
         Self {
-            #[cfg(feature = "preview-image")]
-            uuid: uuid::Uuid::new_v4().to_string(),
+            feed_url,
             title: title.as_ref().to_string(),
+            guid: guid.unwrap_or(link.as_ref()).to_string(),
             link: link.as_ref().to_string(),
             #[cfg(feature = "preview-image")]
-            image_url: image,
+            images,
+            #[cfg(feature = "preview-image")]
+            image_description,
+            #[cfg(feature = "preview-image")]
+            author: None,
             description: description.as_ref().to_string(),
+            recipient: None,
+            date: None,
         }
     }
 
-    pub async fn post(&self, config: &Config) -> PResult<()> {
-        log::info!("Posting: {}", self.title);
-
-        let base_url = config.base_url.as_str();
-        megalodon::generator(
-            config.sns(),
-            // Remove the last slash.
-            base_url
-                .chars()
-                .take(base_url.len() - 1)
-                .collect::<String>(),
-            Some(config.bot_token.to_owned()),
-            None,
-        )
-        .post_status(
-            format!("{}\n\n{}\n\n{}", self.title, self.description, self.link),
-            self.options(config).await.as_ref().ok(),
-        )
-        .await?;
-        log::info!("Posted: {} successfully.", self.title);
+    /// Posts the content using the account client.
+    #[tracing::instrument(
+        name = "item",
+        skip_all,
+        fields(feed = %self.feed_url, guid = %self.guid, account = %account, status_id)
+    )]
+    pub async fn post(
+        &self,
+        client: &(dyn Megalodon + Send + Sync),
+        account: &str,
+        config: &Config,
+    ) -> PResult<()> {
+        tracing::info!("Posting: {}", self.title);
+        let mut status = format!("{}\n\n{}\n\n{}", self.title, self.description, self.link);
+        let mut options = match self.options(client, config).await {
+            Ok(options) => Some(options),
+            #[cfg(feature = "preview-image")]
+            Err(err @ PError::ImageTimeout(_)) if config.drop_on_media_timeout => return Err(err),
+            Err(err) => {
+                tracing::warn!("Posting without media: {}", err);
+                None
+            }
+        };
+        if let Some(recipient) = &self.recipient {
+            status = format!("@{recipient} {status}");
+            options.get_or_insert_with(Default::default).visibility =
+                Some(StatusVisibility::Direct);
+        }
+        let status = client.post_status(status, options.as_ref()).await?;
+        tracing::Span::current().record("status_id", status.json.id.as_str());
+        tracing::info!("Posted: {} successfully.", self.title);
         Ok(())
     }
 
     /// Returns the options for the post.
     /// Will contain the image if the `with-image` feature is enabled.
     #[cfg(feature = "preview-image")]
-    pub async fn options(&self, config: &Config) -> PResult<PostStatusInputOptions> {
-        let image = self.image_url.clone().unwrap_or_else(|| {
-            fs::canonicalize(&config.default_preview_image)
-                .expect("This should not happen.")
-                .display()
-                .to_string()
-        });
-        let preview = self.create_preview(&image, config)?;
-        log::debug!("Image src: {}", image);
-        log::debug!("Feed preview: {}", preview.display());
-        let image_id = get_image_id(preview, config).await?;
-        fs::remove_file(format!("{}.html", self.uuid))?;
-        fs::remove_file(format!("{}.png", self.uuid))?;
-        log::info!("Image preview and html template removed");
+    pub async fn options(
+        &self,
+        client: &(dyn Megalodon + Send + Sync),
+        config: &Config,
+    ) -> PResult<PostStatusInputOptions> {
+        let image = self.image(config).await;
+        if config.attach_images {
+            let Some((source, image)) = image else {
+                return Ok(PostStatusInputOptions::default());
+            };
+            // The media description describes the media images, not the other ones.
+            let is_media = matches!(
+                source,
+                ImageSource::MediaContent | ImageSource::MediaThumbnail
+            );
+            let alt_text = match &self.image_description {
+                Some(description) if is_media => truncate(description, config.max_alt_text_length),
+                _ => self.alt_text(config),
+            };
+            let image_id = get_image_id(image, &alt_text, client, config).await?;
+            return Ok(PostStatusInputOptions {
+                media_ids: Some(vec![image_id]),
+                ..Default::default()
+            });
+        }
+        // The preview files are removed with the directory when it is dropped,
+        // even if the preview or the upload fails.
+        let dir = tempfile::Builder::new().prefix("pleroma-rss-").tempdir()?;
+        let image = image
+            .map(|(_, image)| image)
+            .or_else(|| config.default_preview_image.clone())
+            .ok_or_else(|| PError::Preview("There is no default preview image".to_owned()))?;
+        let image = fs::canonicalize(image)?.display().to_string();
+        let preview = self.create_preview(dir.path(), &image, config).await?;
+        tracing::debug!("Image src: {}", image);
+        tracing::debug!("Feed preview: {}", preview.display());
+        let image_id = get_image_id(preview, &self.alt_text(config), client, config).await?;
         Ok(PostStatusInputOptions {
             media_ids: Some(vec![image_id]),
             ..Default::default()
         })
     }
 
+    /// Returns the first item image that can be downloaded, with its source.
+    /// The sources are tried in the `--image-sources` order, and the images of a source
+    /// in the feed order.
+    #[cfg(feature = "preview-image")]
+    async fn image(&self, config: &Config) -> Option<(ImageSource, PathBuf)> {
+        for source in &config.image_sources {
+            let urls = match source {
+                // The item page of a user feed may be a private address.
+                ImageSource::OgImage if self.recipient.is_some() => continue,
+                ImageSource::OgImage => match images::page_image(&self.link, config).await {
+                    Ok(url) => url.into_iter().collect(),
+                    Err(err) => {
+                        tracing::warn!("Error getting the page image: {}", err);
+                        continue;
+                    }
+                },
+                _ => self
+                    .images
+                    .iter()
+                    .filter(|(found, _)| found == source)
+                    .map(|(_, url)| url.clone())
+                    .collect::<Vec<_>>(),
+            };
+            for url in urls {
+                match image_cache::fetch(&url, self.recipient.is_some(), config).await {
+                    Ok(image) => return Some((*source, image)),
+                    Err(err) => tracing::warn!("The item image {} is not used: {}", url, err),
+                }
+            }
+        }
+        None
+    }
+
     /// Returns the options for the post.
     /// Will be empty if the `with-image` feature is disabled.
     #[cfg(not(feature = "preview-image"))]
-    pub async fn options(&self, _config: &Config) -> PResult<PostStatusInputOptions> {
+    pub async fn options(
+        &self,
+        _client: &(dyn Megalodon + Send + Sync),
+        _config: &Config,
+    ) -> PResult<PostStatusInputOptions> {
         Ok(PostStatusInputOptions::default())
     }
 
-    /// Create a preview image.
+    /// Create a preview image in the directory.
+    /// It is rendered natively if there is a preview layout, otherwise by wkhtmltoimage.
     #[cfg(feature = "preview-image")]
-    pub fn create_preview(&self, image: &str, config: &Config) -> PResult<PathBuf> {
-        log::debug!(
+    pub async fn create_preview(
+        &self,
+        dir: &Path,
+        image: &str,
+        config: &Config,
+    ) -> PResult<PathBuf> {
+        #[cfg(feature = "native-preview")]
+        if let Some(layout) = &config.preview_layout {
+            return self.render_preview(dir, image, layout).await;
+        }
+        let Some(template) = &config.preview_image_template else {
+            return Err(PError::Preview("There is no preview template".to_owned()));
+        };
+        if template.extension().is_some_and(|ext| ext == "svg") {
+            return self.render_svg(dir, image, template, config).await;
+        }
+        tracing::debug!(
             "Creating preview for: {}. The template is: {}",
             self.title,
-            config.preview_image_template.display()
+            template.display()
         );
-        let html_content = fs::read_to_string(config.preview_image_template.as_path())?;
-        log::info!("Template readded successfully.");
-        let html_content = html_content
-            .replace("{{title}}", &self.title)
-            .replace(
-                "{{description}}",
-                &self
-                    .description
-                    .chars()
-                    .enumerate()
-                    .take_while(|(idx, c)| idx < &320 || c != &' ')
-                    .map(|(_, c)| c)
-                    .collect::<String>(),
-            )
-            .replace("{{link}}", &self.link)
-            .replace("{{image-src}}", image);
-        log::info!("Template replaced successfully.");
-        let content_path = format!("{}.html", self.uuid);
-        let image_path = format!("{}.png", self.uuid);
+        let html_content = fs::read_to_string(template)?;
+        tracing::info!("Template readded successfully.");
+        let html_content = self.fill_template(&html_content, image);
+        tracing::info!("Template replaced successfully.");
+        let content_path = dir.join("preview.html");
+        let image_path = dir.join("preview.png");
         fs::write(&content_path, html_content)?;
-        fs::File::create(&image_path)?;
-        log::info!("Template written successfully.");
-        std::process::Command::new("wkhtmltoimage")
-            .arg("--enable-local-file-access")
+        tracing::info!("Template written successfully.");
+        // Only the preview directory and the item image can be read by the template.
+        let status = std::process::Command::new("wkhtmltoimage")
+            .arg("--disable-local-file-access")
+            .arg("--allow")
+            .arg(dir)
+            .arg("--allow")
+            .arg(image)
             .arg("--enable-smart-width")
-            .arg(content_path)
+            .arg(&content_path)
             .arg(&image_path)
             .spawn()?
             .wait()?;
+        if !status.success() {
+            return Err(PError::Preview(format!(
+                "wkhtmltoimage failed with {status}"
+            )));
+        }
+        fs::canonicalize(&image_path).map_err(From::from)
+    }
+
+    /// Renders the SVG template in-process, the placeholders are XML escaped.
+    #[cfg(feature = "preview-image")]
+    async fn render_svg(
+        &self,
+        dir: &Path,
+        image: &str,
+        template: &Path,
+        config: &Config,
+    ) -> PResult<PathBuf> {
+        tracing::debug!(
+            "Rendering SVG preview for: {}. The template is: {}",
+            self.title,
+            template.display()
+        );
+        let svg = self.fill_template(&fs::read_to_string(template)?, image);
+        let image_path = dir.join("preview.png");
+        super::svg::render(
+            &svg,
+            template.parent(),
+            config.preview_size,
+            &config.preview_fonts,
+            fs::read(image).ok().map(|data| (image, data)),
+            &image_path,
+        )?;
+        tracing::info!("Preview rendered successfully.");
+        fs::canonicalize(&image_path).map_err(From::from)
+    }
+
+    /// Replaces the placeholders of the HTML or SVG template, the values are escaped.
+    #[cfg(feature = "preview-image")]
+    fn fill_template(&self, template: &str, image: &str) -> String {
+        let escape = |text: &str| quick_xml::escape::escape(text).into_owned();
+        template
+            .replace("{{title}}", &escape(&self.title))
+            .replace("{{description}}", &escape(&self.short_description()))
+            .replace("{{link}}", &escape(&self.link))
+            .replace(
+                "{{author}}",
+                &escape(self.author.as_deref().unwrap_or_default()),
+            )
+            .replace("{{image-src}}", &escape(image))
+    }
+
+    /// Returns the alt text of the uploaded image from the `--alt-text-template`,
+    /// truncated to `--max-alt-text-length` characters.
+    #[cfg(feature = "preview-image")]
+    fn alt_text(&self, config: &Config) -> String {
+        let alt_text = config
+            .alt_text_template
+            .replace("{{title}}", &self.title)
+            .replace("{{description}}", &self.short_description())
+            .replace("{{link}}", &self.link)
+            .replace("{{author}}", self.author.as_deref().unwrap_or_default());
+        truncate(&alt_text, config.max_alt_text_length)
+    }
+
+    /// Returns the first 320 characters of the description, in full words.
+    #[cfg(feature = "preview-image")]
+    fn short_description(&self) -> String {
+        self.description
+            .chars()
+            .enumerate()
+            .take_while(|(idx, c)| idx < &320 || c != &' ')
+            .map(|(_, c)| c)
+            .collect()
+    }
+
+    /// Renders the preview image natively with the layout.
+    #[cfg(feature = "native-preview")]
+    async fn render_preview(&self, dir: &Path, image: &str, layout: &Path) -> PResult<PathBuf> {
+        tracing::debug!(
+            "Rendering preview for: {}. The layout is: {}",
+            self.title,
+            layout.display()
+        );
+        let layout = preview::Layout::read(layout)?;
+        let image = fs::read(image).ok();
+        let domain = Url::parse(&self.link)
+            .ok()
+            .and_then(|url| url.host_str().map(ToOwned::to_owned))
+            .or_else(|| self.feed_url.host_str().map(ToOwned::to_owned))
+            .unwrap_or_default();
+        let card = preview::Card {
+            title: &self.title,
+            description: &self.description,
+            domain: domain.trim_start_matches("www."),
+            image: image.as_deref(),
+        };
+        let image_path = dir.join("preview.png");
+        layout
+            .render(&card)?
+            .save_with_format(&image_path, ::image::ImageFormat::Png)
+            .map_err(|err| PError::Preview(err.to_string()))?;
+        tracing::info!("Preview rendered successfully.");
         fs::canonicalize(&image_path).map_err(From::from)
     }
 }
@@ -162,76 +420,469 @@ impl Feed {
     pub fn new(url: Url, only_new: bool) -> Self {
         Self {
             url,
+            moved_to: None,
             last_post: if only_new {
                 Some(chrono::Utc::now().timestamp() as u64)
             } else {
                 None
             },
+            accounts: Vec::new(),
+            title: None,
+            tags: Vec::new(),
+            failures: 0,
+            outages: 0,
+            disabled: false,
+            retry_at: None,
+            full_text: None,
+            public_only: false,
         }
     }
 
+    /// Returns `true` if the feed should be checked now.
+    pub fn is_due(&self) -> bool {
+        !self.disabled && self.retry_at.is_none_or(|retry_at| retry_at <= Utc::now())
+    }
+
+    /// Resets the failures after a successful check.
+    pub fn record_success(&mut self) {
+        if self.failures + self.outages > 0 {
+            tracing::info!(
+                "The feed {} is back after {} failure(s).",
+                self.url,
+                self.failures + self.outages
+            );
+        }
+        self.failures = 0;
+        self.outages = 0;
+        self.retry_at = None;
+    }
+
+    /// Records a failed check, the next check is delayed exponentially starting from `delay` seconds.
+    /// The feed is disabled after `max_failures` consecutive failures of the feed itself, or if
+    /// it is gone (`410`). The transient failures, e.g. a network outage, never disable it.
+    /// Returns `true` if the feed is disabled now.
+    pub fn record_failure(&mut self, err: &PError, delay: u64, max_failures: u32) -> bool {
+        let attempts = if err.class() == ErrorClass::Feed {
+            self.failures += 1;
+            if self.failures >= max_failures
+                || matches!(err, PError::FeedStatus(_, status) if *status == StatusCode::GONE.as_u16())
+            {
+                tracing::warn!("The feed {} is disabled: {}", self.url, err);
+                self.disabled = true;
+                self.retry_at = None;
+                return true;
+            }
+            self.failures
+        } else {
+            self.outages += 1;
+            self.failures + self.outages
+        };
+        let delay = delay
+            .saturating_mul(2_u64.saturating_pow(attempts - 1))
+            .min(MAX_BACKOFF);
+        tracing::warn!(
+            "The feed {} failed {} time(s), retrying after {} seconds: {}",
+            self.url,
+            attempts,
+            delay,
+            err
+        );
+        self.retry_at = Some(Utc::now() + chrono::Duration::seconds(delay as i64));
+        false
+    }
+
+    /// Returns `true` if the feed items should be posted to the account.
+    /// A feed without accounts is routed to all the accounts.
+    pub fn is_routed_to(&self, account: &str) -> bool {
+        self.accounts.is_empty() || self.accounts.iter().any(|name| name == account)
+    }
+
     /// Checks if a new post has been made.
     /// If a new post has been made, it returns the post content.
-    pub async fn check(&mut self) -> PResult<Vec<Content>> {
-        log::info!("Checking feed: {}", self.url);
-        let feed = reqwest::get(self.url.as_str()).await?.text().await?;
-        log::info!("Feed: {} has been downloaded.", self.url);
+    /// With the link previews, the missing fields of the new items are filled from their pages.
+    #[tracing::instrument(name = "feed", skip_all, fields(url = %self.url))]
+    pub async fn check(&mut self, link_preview: &LinkPreviewOptions) -> PResult<Vec<Content>> {
+        tracing::info!("Checking feed: {}", self.url);
+        // The item links of a user feed may be private addresses.
+        let no_preview;
+        let link_preview = if self.public_only {
+            no_preview = LinkPreviewOptions {
+                enabled: false,
+                ..link_preview.clone()
+            };
+            &no_preview
+        } else {
+            link_preview
+        };
+        let url = self.url.to_string();
+        let timer = metrics::FEED_FETCH_DURATION
+            .with_label_values(&[&url])
+            .start_timer();
+        let feed = self.fetch().await?;
+        timer.observe_duration();
+        tracing::info!("Feed: {} has been downloaded.", self.url);
         let feed = feed.parse::<rss::Channel>()?;
-        log::info!("Feed: {} has been parsed.", self.url);
+        tracing::info!("Feed: {} has been parsed.", self.url);
+        metrics::ITEMS
+            .with_label_values(&[&url, "seen"])
+            .inc_by(feed.items.len() as u64);
         // Start from the last post and go backwards.
         // If a post is newer than the last post, it is added to the list.
-        let feeds: Vec<_> = feed
-            .items
-            .into_iter()
-            .rev()
-            .map(|item| {
-                let date = DateTime::parse_from_rfc2822(
-                    item.pub_date()
-                        .ok_or_else(|| PError::NoPublishDate(self.url.clone()))?,
-                )
-                .map_err(|_| PError::InvalidPublishDate(self.url.clone()))?
-                .timestamp() as u64;
-                Ok::<_, PError>((date, item))
-            })
-            .collect::<PResult<_>>()?;
-        feeds
-            .iter()
-            .filter(|(date, _)| {
-                let status = if let Some(last_post) = self.last_post {
-                    date > &last_post
-                } else {
-                    true
-                };
-                if status {
-                    log::debug!("New post found: {}", date);
-                    self.last_post = Some(*date);
+        // An invalid item is reported and skipped, the other items are still posted.
+        let items_count = feed.items.len();
+        let mut contents = Vec::new();
+        for item in feed.items.iter().rev() {
+            let mut page = None;
+            let date = match self.item_date(item) {
+                Ok(date) => date,
+                Err(err) => {
+                    page = item_page(item, link_preview).await;
+                    match page.as_ref().and_then(|page| page.published()) {
+                        Some(date) => date,
+                        None => {
+                            self.skip_item(&err);
+                            continue;
+                        }
+                    }
                 }
-                status
-            })
-            .map(|(_, item)| {
-                Ok(Content::new(
-                    item.title()
-                        .ok_or_else(|| PError::NoTitle(self.url.clone()))?,
-                    urlencoding::decode(
-                        item.link()
-                            .ok_or_else(|| PError::NoLink(self.url.clone()))?,
-                    )
-                    .unwrap(),
-                    remove_html_tags(
-                        item.description()
-                            .ok_or_else(|| PError::NoDescription(self.url.clone()))?,
-                    ),
-                    #[cfg(feature = "preview-image")]
-                    item.extensions().get("media").and_then(|ext| {
-                        ext.get("content").and_then(|content| {
-                            content
-                                .iter()
-                                .next()
-                                .and_then(|c| c.attrs.get("url").map(ToString::to_string))
-                        })
-                    }),
-                ))
-            })
-            .collect()
+            };
+            if self.last_post.is_some_and(|last_post| date <= last_post) {
+                continue;
+            }
+            tracing::debug!("New post found: {}", date);
+            self.last_post = Some(date);
+            if page.is_none() {
+                page = item_page(item, link_preview).await;
+            }
+            match self.item_content(item, page.as_deref()) {
+                Ok(mut content) => {
+                    content.date = Some(date);
+                    if let Some(max_size) = self.full_text {
+                        self.fill_full_text(&mut content, max_size, link_preview)
+                            .await;
+                    }
+                    contents.push(content);
+                }
+                Err(err) => self.skip_item(&err),
+            }
+        }
+        metrics::ITEMS
+            .with_label_values(&[&url, "filtered"])
+            .inc_by((items_count - contents.len()) as u64);
+        Ok(contents)
+    }
+
+    /// Downloads the feed, following the redirects.
+    /// A permanent redirect (`301` or `308`) changes the url that the feed is fetched from.
+    async fn fetch(&mut self) -> PResult<String> {
+        let mut url = self.moved_to.clone().unwrap_or_else(|| self.url.clone());
+        // Only a chain of permanent redirects changes the feed url.
+        let mut is_permanent = true;
+        for _ in 0..=MAX_REDIRECTS {
+            let response = if self.public_only {
+                if !public::is_public_url(&url) {
+                    return Err(PError::NotPublic(url));
+                }
+                public::FEEDS_CLIENT.get(url.as_str()).send().await?
+            } else {
+                CLIENT.get(url.as_str()).send().await?
+            };
+            let status = response.status();
+            metrics::FEED_HTTP_RESPONSES
+                .with_label_values(&[self.url.as_str(), &metrics::status_class(status)])
+                .inc();
+            if !status.is_redirection() {
+                if !status.is_success() {
+                    return Err(PError::FeedStatus(self.url.clone(), status.as_u16()));
+                }
+                return response.text().await.map_err(From::from);
+            }
+            url = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok())
+                .ok_or_else(|| PError::FeedStatus(self.url.clone(), status.as_u16()))?;
+            is_permanent &= matches!(
+                status,
+                StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+            );
+            if is_permanent {
+                tracing::warn!("The feed {} is moved to: {}", self.url, url);
+                self.moved_to = (url != self.url).then(|| url.clone());
+            }
+        }
+        Err(PError::TooManyRedirects(self.url.clone()))
+    }
+
+    /// Returns the publish date of the item as a timestamp.
+    fn item_date(&self, item: &rss::Item) -> PResult<u64> {
+        let date = item
+            .pub_date()
+            .ok_or_else(|| PError::NoPublishDate(self.url.clone()))?;
+        Ok(DateTime::parse_from_rfc2822(date)
+            .map_err(|_| PError::InvalidPublishDate(self.url.clone()))?
+            .timestamp() as u64)
+    }
+
+    /// Returns the content of the item.
+    /// The missing or blank fields are filled from the item page metadata, if it is fetched.
+    fn item_content(&self, item: &rss::Item, page: Option<&PageMeta>) -> PResult<Content> {
+        let link = item
+            .link()
+            .ok_or_else(|| PError::NoLink(self.url.clone()))?;
+        let title = or_page_value(
+            item.title().map(ToOwned::to_owned),
+            page.and_then(|page| page.title.clone()),
+        )
+        .ok_or_else(|| PError::NoTitle(self.url.clone()))?;
+        let description = or_page_value(
+            item.description().map(remove_html_tags),
+            page.and_then(|page| page.description.clone()),
+        )
+        .ok_or_else(|| PError::NoDescription(self.url.clone()))?;
+        #[cfg(feature = "preview-image")]
+        let author = or_page_value(
+            item.author()
+                .or_else(|| {
+                    item.dublin_core_ext()
+                        .and_then(|dc| dc.creators().first())
+                        .map(String::as_str)
+                })
+                .map(ToOwned::to_owned),
+            page.and_then(|page| page.author.clone()),
+        );
+        Ok(Content {
+            #[cfg(feature = "preview-image")]
+            author,
+            ..Content::new(
+                self.url.clone(),
+                title,
+                urlencoding::decode(link).unwrap_or(link.into()),
+                item.guid().map(|guid| guid.value()),
+                description,
+                #[cfg(feature = "preview-image")]
+                images::discover(item, link),
+                #[cfg(feature = "preview-image")]
+                images::media_description(item),
+            )
+        })
+    }
+
+    /// Replaces the description of the content with the main content of its article.
+    /// The description is kept if the article has no content or can not be fetched.
+    async fn fill_full_text(
+        &self,
+        content: &mut Content,
+        max_size: usize,
+        link_preview: &LinkPreviewOptions,
+    ) {
+        match full_text::fetch(&content.link, max_size, link_preview).await {
+            Ok(Some(text)) => content.description = text,
+            Ok(None) => tracing::warn!("The article {} has no content.", content.link),
+            Err(err) => {
+                tracing::warn!("Error fetching the article {}: {}", content.link, err)
+            }
+        }
+    }
+
+    /// Reports an invalid item, the item is skipped.
+    fn skip_item(&self, err: &PError) {
+        tracing::warn!("Skipping an item: {}", err);
+        metrics::FEED_ERRORS
+            .with_label_values(&[self.url.as_str(), err.kind_name()])
+            .inc();
+    }
+}
+
+impl std::fmt::Display for Feed {
+    /// Formats the feed as a feeds file line, the url followed by the feed options.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.url)?;
+        if !self.accounts.is_empty() {
+            write!(f, " accounts={}", encode_list(&self.accounts))?;
+        }
+        if !self.tags.is_empty() {
+            write!(f, " tags={}", encode_list(&self.tags))?;
+        }
+        if let Some(max_size) = self.full_text {
+            write!(f, " full_text={max_size}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if the item misses its title, description or publish date.
+fn is_sparse(item: &rss::Item) -> bool {
+    item.title().is_none_or(|title| title.trim().is_empty())
+        || item
+            .description()
+            .is_none_or(|description| remove_html_tags(description).is_empty())
+        || item.pub_date().is_none_or(|date| date.trim().is_empty())
+}
+
+/// Returns the page metadata of a sparse item, if the link previews are enabled.
+async fn item_page(item: &rss::Item, options: &LinkPreviewOptions) -> Option<Arc<PageMeta>> {
+    if !options.enabled || !is_sparse(item) {
+        return None;
+    }
+    let link = item.link()?;
+    match link_preview::fetch(link, options).await {
+        Ok(page) => Some(page),
+        Err(err) => {
+            tracing::warn!("Error getting the link preview of {}: {}", link, err);
+            None
+        }
+    }
+}
+
+/// Returns the item value, or the page value if the item value is missing or blank.
+fn or_page_value(value: Option<String>, page_value: Option<String>) -> Option<String> {
+    match value {
+        Some(value) if !value.trim().is_empty() => Some(value),
+        value => page_value.or(value),
+    }
+}
+
+/// Truncates the text to `max` characters, the whitespace is collapsed and the
+/// truncated text ends with `…`.
+#[cfg(feature = "preview-image")]
+fn truncate(text: &str, max: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= max {
+        return text;
+    }
+    text.chars().take(max.saturating_sub(1)).collect::<String>() + "…"
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server,
+    };
+
+    use super::*;
+
+    fn feed() -> Feed {
+        Feed::new(Url::parse("https://example.com/feed.xml").unwrap(), false)
+    }
+
+    /// Returns the seconds until the next check of the feed.
+    fn retry_after(feed: &Feed) -> i64 {
+        (feed.retry_at.unwrap() - Utc::now()).num_seconds()
+    }
+
+    #[test]
+    fn feed_failures_back_off() {
+        let mut feed = feed();
+        let err = PError::FeedStatus(feed.url.clone(), 404);
+        for (failures, delay) in [(1, 30), (2, 60), (3, 120), (4, 240)] {
+            assert!(!feed.record_failure(&err, 30, 5));
+            assert_eq!(feed.failures, failures);
+            assert!((delay - 1..=delay).contains(&retry_after(&feed)));
+            assert!(!feed.is_due());
+        }
+        assert!(feed.record_failure(&err, 30, 5));
+        assert!(feed.disabled);
+        assert!(!feed.is_due());
+    }
+
+    #[test]
+    fn transient_failures_do_not_disable() {
+        let mut feed = feed();
+        let err = PError::Io(std::io::Error::other("network is down"));
+        for outages in 1..=20 {
+            assert!(!feed.record_failure(&err, 30, 3));
+            assert_eq!(feed.outages, outages);
+        }
+        assert_eq!(feed.failures, 0);
+        assert!(!feed.disabled);
+        assert_eq!(retry_after(&feed), MAX_BACKOFF as i64 - 1);
+        feed.record_success();
+        assert!(feed.is_due());
+        assert_eq!((feed.failures, feed.outages), (0, 0));
+    }
+
+    #[test]
+    fn gone_feed_is_disabled() {
+        let mut feed = feed();
+        let err = PError::FeedStatus(feed.url.clone(), 410);
+        assert!(feed.record_failure(&err, 30, 10));
+        assert!(!feed.is_due());
+    }
+
+    /// Serves the redirects of the paths (`/<status>/<path>` redirects to `/<path>`),
+    /// and an empty feed at `/feed.xml`. Returns the url of the server.
+    fn redirecting_server() -> String {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+                let path = req.uri().path().trim_start_matches('/');
+                let response = match path.split_once('/') {
+                    Some((status, location)) => Response::builder()
+                        .status(status.parse::<u16>().unwrap())
+                        .header(LOCATION, format!("/{location}"))
+                        .body(Body::empty()),
+                    None => Response::builder().body(Body::from(
+                        "<rss version=\"2.0\"><channel><title>Feed</title>\
+                         <link>https://example.com</link><description>Feed</description>\
+                         </channel></rss>",
+                    )),
+                };
+                Ok::<_, Infallible>(response.unwrap())
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    #[tokio::test]
+    async fn permanent_redirects_move_the_feed() {
+        let server = redirecting_server();
+        let mut feed = Feed::new(
+            Url::parse(&format!("{server}/301/308/feed.xml")).unwrap(),
+            false,
+        );
+        feed.fetch().await.unwrap();
+        assert_eq!(
+            feed.moved_to.unwrap().as_str(),
+            format!("{server}/feed.xml")
+        );
+    }
+
+    #[tokio::test]
+    async fn temporary_redirects_do_not_move_the_feed() {
+        let server = redirecting_server();
+        let mut feed = Feed::new(
+            Url::parse(&format!("{server}/301/302/feed.xml")).unwrap(),
+            false,
+        );
+        feed.fetch().await.unwrap();
+        // Only the permanent part of the chain is kept.
+        assert_eq!(
+            feed.moved_to.unwrap().as_str(),
+            format!("{server}/302/feed.xml")
+        );
+        let mut feed = Feed::new(
+            Url::parse(&format!("{server}/302/301/feed.xml")).unwrap(),
+            false,
+        );
+        feed.fetch().await.unwrap();
+        assert_eq!(feed.moved_to, None);
+    }
+
+    #[tokio::test]
+    async fn too_many_redirects() {
+        let server = redirecting_server();
+        let url = format!("{server}{}/feed.xml", "/301".repeat(MAX_REDIRECTS + 1));
+        let mut feed = Feed::new(Url::parse(&url).unwrap(), false);
+        assert!(matches!(
+            feed.fetch().await,
+            Err(PError::TooManyRedirects(_))
+        ));
     }
 }
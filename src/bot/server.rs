@@ -0,0 +1,118 @@
+use std::{convert::Infallible, net::SocketAddr, time::Duration};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde_json::json;
+use tokio::sync::watch;
+
+use super::{shutdown::Shutdown, status::Status};
+use crate::{errors::Result as PResult, metrics};
+
+/// The timeout of checking an account in `/readyz`.
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+/// The time between the checks of the accounts, `/readyz` returns the last check.
+const READY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The response of `/readyz`, the result of the last accounts check.
+type Readiness = (StatusCode, serde_json::Value);
+
+/// Binds the health and status server, and serves it until the shutdown.
+/// ### Endpoints
+/// - `/healthz`: The process is alive.
+/// - `/readyz`: The instances are reachable and the access tokens are valid.
+///   The accounts are checked in the background every [`READY_INTERVAL`].
+/// - `/status`: The status of every feed as JSON.
+/// - `/metrics`: The Prometheus metrics.
+pub fn spawn(addr: SocketAddr, status: Status, mut shutdown: Shutdown) -> PResult<()> {
+    let server = Server::try_bind(&addr)?;
+    tracing::info!("The status server is listening on: {}", addr);
+    let (ready_sender, ready) = watch::channel((
+        StatusCode::SERVICE_UNAVAILABLE,
+        json!({ "ready": false, "accounts": {} }),
+    ));
+    tokio::spawn(check_accounts(
+        status.clone(),
+        ready_sender,
+        shutdown.clone(),
+    ));
+    let make_service = make_service_fn(move |_| {
+        let status = status.clone();
+        let ready = ready.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(req, status.clone(), ready.clone())
+            }))
+        }
+    });
+    tokio::spawn(async move {
+        let server = server
+            .serve(make_service)
+            .with_graceful_shutdown(async move { shutdown.wait().await });
+        if let Err(err) = server.await {
+            tracing::error!("The status server error: {}", err);
+        }
+    });
+    Ok(())
+}
+
+/// Handles the request.
+async fn handle(
+    req: Request<Body>,
+    status: Status,
+    ready: watch::Receiver<Readiness>,
+) -> Result<Response<Body>, Infallible> {
+    tracing::debug!("Status server request: {} {}", req.method(), req.uri());
+    if req.method() == Method::GET && req.uri().path() == "/metrics" {
+        return Ok(Response::builder()
+            .header("Content-Type", prometheus::TEXT_FORMAT)
+            .body(Body::from(metrics::gather()))
+            .expect("The response is valid"));
+    }
+    let (code, body) = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/healthz") => (StatusCode::OK, json!({ "status": "ok" })),
+        (&Method::GET, "/readyz") => ready.borrow().clone(),
+        (&Method::GET, "/status") => (StatusCode::OK, json!({ "feeds": status.feeds() })),
+        _ => (StatusCode::NOT_FOUND, json!({ "error": "not found" })),
+    };
+    Ok(Response::builder()
+        .status(code)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("The response is valid"))
+}
+
+/// Checks the accounts every [`READY_INTERVAL`] until the shutdown, and sends the results.
+async fn check_accounts(status: Status, ready: watch::Sender<Readiness>, mut shutdown: Shutdown) {
+    loop {
+        ready.send_replace(readyz(&status).await);
+        tokio::select! {
+            _ = shutdown.wait() => break,
+            _ = tokio::time::sleep(READY_INTERVAL) => {}
+        }
+    }
+}
+
+/// Checks that the instances are reachable and the access tokens are valid.
+async fn readyz(status: &Status) -> Readiness {
+    let mut ready = true;
+    let mut accounts = serde_json::Map::new();
+    for account in status.accounts() {
+        let client = account.client();
+        let result = tokio::time::timeout(READY_TIMEOUT, client.verify_account_credentials()).await;
+        let account_status = match result {
+            Ok(Ok(_)) => "ok".to_owned(),
+            Ok(Err(err)) => err.to_string(),
+            Err(_) => "timeout".to_owned(),
+        };
+        ready &= account_status == "ok";
+        accounts.insert(account.name, account_status.into());
+    }
+    let code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, json!({ "ready": ready, "accounts": accounts }))
+}
@@ -0,0 +1,48 @@
+use tokio::sync::watch;
+
+use crate::errors::Result as PResult;
+
+/// The shutdown signal, it is triggered when the bot receives `SIGINT` or `SIGTERM`.
+#[derive(Debug, Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Starts listening for `SIGINT` and `SIGTERM`.
+    pub fn listen() -> PResult<Self> {
+        let (sender, receiver) = watch::channel(false);
+        #[cfg(unix)]
+        let mut sigterm =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => tracing::info!("Received SIGINT."),
+                _ = sigterm.recv() => tracing::info!("Received SIGTERM."),
+            }
+            #[cfg(not(unix))]
+            if tokio::signal::ctrl_c().await.is_ok() {
+                tracing::info!("Received Ctrl+C.");
+            }
+            println!("Shutting down...");
+            sender.send_replace(true);
+            // Keep the sender alive, so the receivers do not see a closed channel.
+            sender.closed().await;
+        });
+        Ok(Self(receiver))
+    }
+
+    /// Returns `true` if the shutdown is triggered.
+    pub fn is_triggered(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Waits until the shutdown is triggered.
+    pub async fn wait(&mut self) {
+        while !self.is_triggered() {
+            if self.0.changed().await.is_err() {
+                // The listener is stopped, the shutdown will never be triggered.
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
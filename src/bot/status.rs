@@ -0,0 +1,138 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use url::Url;
+
+use super::{rss::Content, Feed};
+use crate::config::Account;
+
+/// The runtime status of the bot, it is shared with the status server.
+#[derive(Debug, Clone, Default)]
+pub struct Status(Arc<Mutex<StatusData>>);
+
+/// The status data.
+#[derive(Debug, Default)]
+struct StatusData {
+    /// The bot accounts, used to check if the bot is ready.
+    accounts: Vec<Account>,
+    /// The time of the next poll.
+    next_poll: Option<DateTime<Utc>>,
+    /// The feeds status by their url.
+    feeds: BTreeMap<Url, FeedStatus>,
+}
+
+/// The status of a feed.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FeedStatus {
+    /// The feed url.
+    pub url: Option<Url>,
+    /// The time of the last fetch.
+    pub last_fetch: Option<DateTime<Utc>>,
+    /// The error of the last fetch.
+    pub last_error: Option<String>,
+    /// The last posted item.
+    pub last_posted: Option<PostedItem>,
+    /// The time of the next poll.
+    pub next_poll: Option<DateTime<Utc>>,
+    /// The number of consecutive failures.
+    pub failures: u32,
+    /// The feed is disabled after too many failures.
+    pub disabled: bool,
+}
+
+/// A posted item.
+#[derive(Debug, Clone, Serialize)]
+pub struct PostedItem {
+    /// The item title.
+    pub title: String,
+    /// The item link.
+    pub link: String,
+    /// The account that the item is posted to.
+    pub account: String,
+    /// The time of the post.
+    pub posted_at: DateTime<Utc>,
+}
+
+impl Status {
+    /// Creates a new status for the accounts and feeds.
+    pub fn new(accounts: &[Account], feeds: &[Feed]) -> Self {
+        let status = Self::default();
+        status.update(accounts, feeds);
+        status
+    }
+
+    fn lock(&self) -> MutexGuard<'_, StatusData> {
+        // The data is still valid even if a thread panicked while holding the lock.
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Updates the accounts and the feeds after a reload, removing the status of the removed feeds.
+    pub fn update(&self, accounts: &[Account], feeds: &[Feed]) {
+        let mut data = self.lock();
+        data.accounts = accounts.to_vec();
+        data.feeds
+            .retain(|url, _| feeds.iter().any(|feed| &feed.url == url));
+        for feed in feeds {
+            let status = data.feeds.entry(feed.url.clone()).or_default();
+            status.failures = feed.failures;
+            status.disabled = feed.disabled;
+        }
+    }
+
+    /// Records the result of fetching the feed.
+    pub fn feed_fetched(&self, feed: &Feed, error: Option<String>) {
+        let mut data = self.lock();
+        let status = data.feeds.entry(feed.url.clone()).or_default();
+        status.last_fetch = Some(Utc::now());
+        status.last_error = error;
+        status.failures = feed.failures;
+        status.disabled = feed.disabled;
+        status.next_poll = feed.retry_at;
+    }
+
+    /// Records the posted content.
+    pub fn item_posted(&self, content: &Content, account: &str) {
+        let mut data = self.lock();
+        data.feeds
+            .entry(content.feed_url.clone())
+            .or_default()
+            .last_posted = Some(PostedItem {
+            title: content.title.clone(),
+            link: content.link.clone(),
+            account: account.to_owned(),
+            posted_at: Utc::now(),
+        });
+    }
+
+    /// Sets the time of the next poll.
+    pub fn set_next_poll(&self, next_poll: DateTime<Utc>) {
+        self.lock().next_poll = Some(next_poll);
+    }
+
+    /// Returns the bot accounts.
+    pub fn accounts(&self) -> Vec<Account> {
+        self.lock().accounts.clone()
+    }
+
+    /// Returns the status of the feeds.
+    pub fn feeds(&self) -> Vec<FeedStatus> {
+        let data = self.lock();
+        data.feeds
+            .iter()
+            .map(|(url, feed)| FeedStatus {
+                url: Some(url.clone()),
+                // A failing feed is checked in the first poll after its retry time.
+                next_poll: if feed.disabled {
+                    None
+                } else {
+                    feed.next_poll.max(data.next_poll)
+                },
+                ..feed.clone()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use once_cell::sync::Lazy;
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{fontdb::Database, ImageHrefResolver, Options, Tree},
+};
+
+use crate::errors::{Error as PError, Result as PResult};
+
+/// The fonts that are bundled in the binary, so the text is rendered without system fonts.
+const BUNDLED_FONTS: [&[u8]; 2] = [
+    include_bytes!("../../assets/fonts/DejaVuSans.ttf"),
+    include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf"),
+];
+/// The family of the bundled fonts, the default font without the `fonts`.
+const BUNDLED_FAMILY: &str = "DejaVu Sans";
+
+/// The font database of the `fonts` and its default family, with the `fonts` it is made of.
+type Fonts = (Vec<PathBuf>, Arc<Database>, String);
+/// The last font database, loading the system fonts is too slow to do it for every preview.
+/// It is made again when the `fonts` change, e.g. after a config reload.
+static FONTS: Lazy<Mutex<Option<Fonts>>> = Lazy::new(Default::default);
+
+/// Renders the SVG preview template to a PNG file.
+///
+/// The relative paths in the template are relative to `resources_dir`. The `fonts` are
+/// font files or directories, loaded after the system and the bundled fonts, and the first
+/// one is the default font. Without them, the bundled DejaVu Sans is the default font.
+/// The `image` is the path and the data of the item image in the template.
+pub fn render(
+    svg: &str,
+    resources_dir: Option<&Path>,
+    size: Option<(u32, u32)>,
+    fonts: &[PathBuf],
+    image: Option<(&str, Vec<u8>)>,
+    output: &Path,
+) -> PResult<()> {
+    let (fontdb, font_family) = font_database(fonts)?;
+    let mut options = Options {
+        resources_dir: resources_dir.map(Path::to_path_buf),
+        fontdb,
+        font_family,
+        ..Default::default()
+    };
+    if let Some((url, data)) = image {
+        let url = url.to_owned();
+        let data = Arc::new(data);
+        let resolve_data = ImageHrefResolver::default_data_resolver();
+        let resolve_string = ImageHrefResolver::default_string_resolver();
+        options.image_href_resolver.resolve_string = Box::new(move |href, options| {
+            if href == url {
+                // The downloaded image, its format is detected from its content.
+                resolve_data("text/plain", Arc::clone(&data), options)
+            } else {
+                resolve_string(href, options)
+            }
+        });
+    }
+    let tree = Tree::from_str(svg, &options)
+        .map_err(|err| PError::Preview(format!("Invalid SVG template: {err}")))?;
+    let tree_size = tree.size().to_int_size();
+    let (width, height) = size.unwrap_or((tree_size.width(), tree_size.height()));
+    let mut pixmap = Pixmap::new(width, height)
+        .ok_or_else(|| PError::Preview(format!("Invalid preview size {width}x{height}")))?;
+    let transform = Transform::from_scale(
+        width as f32 / tree.size().width(),
+        height as f32 / tree.size().height(),
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+    pixmap
+        .save_png(output)
+        .map_err(|err| PError::Preview(err.to_string()))
+}
+
+/// Returns the font database of the `fonts` and its default family, it is loaded once.
+fn font_database(fonts: &[PathBuf]) -> PResult<(Arc<Database>, String)> {
+    let mut cache = FONTS.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some((cached, fontdb, family)) = cache.as_ref() {
+        if cached == fonts {
+            return Ok((Arc::clone(fontdb), family.clone()));
+        }
+    }
+    tracing::debug!("Loading the preview fonts.");
+    let mut fontdb = Database::new();
+    fontdb.load_system_fonts();
+    for font in BUNDLED_FONTS {
+        fontdb.load_font_data(font.to_vec());
+    }
+    let bundled_faces = fontdb.len();
+    for font in fonts {
+        if font.is_dir() {
+            fontdb.load_fonts_dir(font);
+        } else {
+            fontdb.load_font_file(font)?;
+        }
+    }
+    let family = fontdb
+        .faces()
+        .nth(bundled_faces)
+        .and_then(|face| face.families.first())
+        .map(|(family, _)| family.clone())
+        .unwrap_or_else(|| BUNDLED_FAMILY.to_owned());
+    let fontdb = Arc::new(fontdb);
+    *cache = Some((fonts.to_vec(), Arc::clone(&fontdb), family.clone()));
+    Ok((fontdb, family))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fonts_are_loaded_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20">
+            <text x="0" y="15">Feed</text></svg>"#;
+        for name in ["first.png", "second.png"] {
+            render(svg, None, Some((80, 40)), &[], None, &dir.path().join(name)).unwrap();
+        }
+        let (first, family) = font_database(&[]).unwrap();
+        let (second, _) = font_database(&[]).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(family, BUNDLED_FAMILY);
+        let size = image::image_dimensions(dir.path().join("second.png")).unwrap();
+        assert_eq!(size, (80, 40));
+    }
+}
@@ -0,0 +1,66 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{cli::RunArgs, errors::Result as PResult};
+
+/// Watches the feeds file and the config file, to reload them when they are modified
+/// or when the bot receives `SIGHUP`.
+#[derive(Debug)]
+pub struct Watcher {
+    /// The watched files and their last modification time.
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    #[cfg(unix)]
+    sighup: tokio::signal::unix::Signal,
+}
+
+impl Watcher {
+    /// Creates a new watcher for the feeds file and the config file.
+    pub fn new(cli: &RunArgs) -> PResult<Self> {
+        Ok(Self {
+            files: std::iter::once(&cli.feeds_file)
+                .chain(&cli.config)
+                .map(|file| (file.clone(), modified(file)))
+                .collect(),
+            #[cfg(unix)]
+            sighup: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
+        })
+    }
+
+    /// Waits for the given duration, returns `true` if the config should be reloaded.
+    /// Returns early if the bot receives `SIGHUP`.
+    pub async fn wait(&mut self, duration: Duration) -> bool {
+        #[cfg(unix)]
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {}
+            _ = self.sighup.recv() => {
+                tracing::info!("Received SIGHUP.");
+                self.is_modified();
+                return true;
+            }
+        }
+        #[cfg(not(unix))]
+        tokio::time::sleep(duration).await;
+        self.is_modified()
+    }
+
+    /// Returns `true` if any of the watched files is modified since the last check.
+    fn is_modified(&mut self) -> bool {
+        let mut is_modified = false;
+        for (file, last_modified) in &mut self.files {
+            let modified = modified(file);
+            if modified != *last_modified {
+                tracing::info!("The file {} is modified.", file.display());
+                *last_modified = modified;
+                is_modified = true;
+            }
+        }
+        is_modified
+    }
+}
+
+/// Returns the last modification time of the file.
+fn modified(file: &Path) -> Option<SystemTime> {
+    file.metadata().and_then(|meta| meta.modified()).ok()
+}
@@ -1,2 +1,7 @@
 mod parser;
-pub use parser::Cli;
+#[cfg(feature = "preview-image")]
+pub use parser::ImageSource;
+pub use parser::{
+    CheckFeedsArgs, Cli, Command, ExportArgs, FeedsFormat, ImportArgs, ListArgs, LogFormat,
+    LoginArgs, RunArgs,
+};
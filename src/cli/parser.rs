@@ -1,34 +1,233 @@
 use crate::{errors::Result as Presult, utils};
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::{ffi::OsString, path::PathBuf};
 
 /// The CLI parser. This is the main entry point for the CLI. It parses the CLI arguments.
-#[derive(Parser)]
+#[derive(Debug, Parser)]
 #[command(version, about, verbatim_doc_comment, long_about = None)]
 pub struct Cli {
-    /// Your bot access token.
-    #[arg(short, long, value_name = "TOKEN")]
-    pub access_token: String,
+    /// The format of the logs.
+    #[arg(long, value_name = "FORMAT", default_value = "pretty", global = true)]
+    pub log_format: LogFormat,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// The format of the logs.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable logs.
+    Pretty,
+    /// One JSON object per line, with the span fields.
+    Json,
+}
+
+/// A source of the item images.
+#[cfg(feature = "preview-image")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImageSource {
+    /// The `media:content` image.
+    MediaContent,
+    /// The `enclosure` with an image type.
+    Enclosure,
+    /// The `media:thumbnail` image.
+    MediaThumbnail,
+    /// The `itunes:image` image.
+    ItunesImage,
+    /// The first `<img>` in the item description or content.
+    Inline,
+    /// The `og:image` or `twitter:image` of the item page, the page is downloaded.
+    OgImage,
+}
+
+/// The CLI subcommands.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the bot, this is the default subcommand.
+    Run(RunArgs),
+    /// Check the feeds one time, post the new items and exit.
+    Once(RunArgs),
+    /// Validate the config and the feeds file, and report the problems.
+    Check(RunArgs),
+    /// Fetch every feed in the feeds file, and report the problems of each feed.
+    CheckFeeds(CheckFeedsArgs),
+    /// List the feeds with their last seen state.
+    List(ListArgs),
+    /// Import the feeds from a feeds file to the feeds file.
+    Import(ImportArgs),
+    /// Export the feeds file.
+    Export(ExportArgs),
+    /// Login to an instance and write the access token to the config file.
+    Login(LoginArgs),
+}
+
+/// The arguments of the `run`, `once` and `check` subcommands.
+#[derive(Args)]
+pub struct RunArgs {
+    /// The config file that contains the bot accounts.
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Your bot access token. Prefer the `PLEROMA_RSS_TOKEN` environment variable or `--access-token-file`.
+    #[arg(short, long, value_name = "TOKEN", requires = "base_url")]
+    pub access_token: Option<String>,
+    /// The file that contains your bot access token.
+    #[arg(short = 'A', long, value_name = "PATH", requires = "base_url")]
+    pub access_token_file: Option<PathBuf>,
     /// The file that contains the feeds.
     #[arg(short, long, value_name = "PATH")]
     pub feeds_file: PathBuf,
+    /// The file that keeps the last seen state of the feeds between the runs.
+    #[arg(short = 'S', long, value_name = "PATH")]
+    pub state_file: Option<PathBuf>,
     /// The server URL.
-    #[arg(short, long, value_name = "URL")]
-    pub base_url: url::Url,
+    #[arg(short, long, value_name = "URL", required_unless_present = "config")]
+    pub base_url: Option<url::Url>,
     /// The sleep time between each feed in seconds.
     #[arg(short = 's', long, value_name = "SECONDS", default_value = "1")]
     pub items_sleep: u64,
     /// The sleep time after end all feeds (wait for new items) in seconds.
     #[arg(short, long, value_name = "SECONDS", default_value = "30")]
     pub watting_new: u64,
+    /// The number of consecutive failures before disabling a feed.
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value = "10",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub max_failures: u32,
+    /// The account to notify about the problems of the bot, e.g. `admin@example.com`.
+    #[arg(long, value_name = "ACCOUNT")]
+    pub admin: Option<String>,
+    /// The minimum time between the admin notifications in seconds.
+    #[arg(long, value_name = "SECONDS", default_value = "3600")]
+    pub admin_interval: u64,
+    /// An account that can send commands to the bot by mentioning it, can be repeated.
+    /// The access tokens need the notifications scopes, see `login --commands`.
+    #[arg(long, value_name = "ACCOUNT")]
+    pub allow: Vec<String>,
+    /// The file that keeps the subscriptions of the users. Without it, the users can not subscribe.
+    #[arg(long, value_name = "PATH")]
+    pub subscriptions_file: Option<PathBuf>,
+    /// The maximum number of subscriptions of a user.
+    #[arg(long, value_name = "COUNT", default_value = "5")]
+    pub max_subscriptions: usize,
+    /// Fill the missing title, description and publish date of the items from the
+    /// Open Graph tags of their pages.
+    #[arg(long)]
+    pub link_previews: bool,
+    /// The maximum time to download an item page in seconds.
+    #[arg(long, value_name = "SECONDS", default_value = "10")]
+    pub link_preview_timeout: u64,
+    /// The time to keep the metadata of an item page in seconds.
+    #[arg(long, value_name = "SECONDS", default_value = "86400")]
+    pub link_preview_cache_ttl: u64,
+    /// The minimum time between the requests to the same domain in seconds.
+    #[arg(long, value_name = "SECONDS", default_value = "1")]
+    pub link_preview_interval: u64,
     /// The HTML template for the preview image.
     #[cfg(feature = "preview-image")]
+    #[cfg_attr(
+        not(feature = "native-preview"),
+        arg(required_unless_present = "attach_images")
+    )]
+    #[cfg_attr(
+        feature = "native-preview",
+        arg(required_unless_present_any = ["preview_layout", "attach_images"])
+    )]
     #[arg(short = 't', long, value_name = "PATH")]
-    pub preview_image_template: PathBuf,
+    pub preview_image_template: Option<PathBuf>,
+    /// The size of the preview image rendered from an SVG template, e.g. `1200x630`.
+    /// The template size is used if not set.
+    #[cfg(feature = "preview-image")]
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size)]
+    pub preview_size: Option<(u32, u32)>,
+    /// A font file or a directory of fonts for the SVG template, can be repeated.
+    /// The first font is the default one.
+    #[cfg(feature = "preview-image")]
+    #[arg(long, value_name = "PATH")]
+    pub preview_font: Vec<PathBuf>,
+    /// The TOML layout of the preview image, the preview is rendered without wkhtmltoimage.
+    #[cfg(feature = "native-preview")]
+    #[arg(long, value_name = "PATH", conflicts_with = "preview_image_template")]
+    pub preview_layout: Option<PathBuf>,
     /// The default image if the feed does not have an image.
     #[cfg(feature = "preview-image")]
-    #[arg(short = 'i', long, value_name = "PATH")]
-    pub default_preview_image: PathBuf,
+    #[arg(
+        short = 'i',
+        long,
+        value_name = "PATH",
+        required_unless_present = "attach_images"
+    )]
+    pub default_preview_image: Option<PathBuf>,
+    /// The sources of the item images, in the order they are tried.
+    #[cfg(feature = "preview-image")]
+    #[arg(
+        long,
+        value_name = "SOURCES",
+        value_delimiter = ',',
+        default_value = "media-content,enclosure,media-thumbnail,itunes-image,inline,og-image"
+    )]
+    pub image_sources: Vec<ImageSource>,
+    /// Attach the item image to the post instead of the preview image.
+    /// The items without an image are posted without attachments.
+    #[cfg(feature = "preview-image")]
+    #[arg(long)]
+    pub attach_images: bool,
+    /// The alt text template of the uploaded images, it can use the `{{title}}`,
+    /// `{{description}}` and `{{link}}` variables. The `media:description` is used for
+    /// the attached media images.
+    #[cfg(feature = "preview-image")]
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = "{{title}}: {{description}}"
+    )]
+    pub alt_text_template: String,
+    /// The maximum length of the alt text in characters, the longer alt texts are truncated.
+    /// It should not exceed the alt text limit of the instance.
+    #[cfg(feature = "preview-image")]
+    #[arg(long, value_name = "CHARS", default_value = "1500")]
+    pub max_alt_text_length: usize,
+    /// The directory of the downloaded item images, `pleroma-rss/images` in the user cache
+    /// directory by default. It must be owned by the bot user.
+    #[cfg(feature = "preview-image")]
+    #[arg(long, value_name = "PATH")]
+    pub image_cache_dir: Option<PathBuf>,
+    /// The time to keep the downloaded item images in seconds.
+    #[cfg(feature = "preview-image")]
+    #[arg(long, value_name = "SECONDS", default_value = "86400")]
+    pub image_cache_ttl: u64,
+    /// The maximum size of an item image in bytes, the larger images are not used.
+    #[cfg(feature = "preview-image")]
+    #[arg(long, value_name = "BYTES", default_value = "10485760")]
+    pub max_image_size: u64,
+    /// The maximum time to download an item image in seconds.
+    #[cfg(feature = "preview-image")]
+    #[arg(long, value_name = "SECONDS", default_value = "10")]
+    pub image_timeout: u64,
+    /// The time between the polls of an uploaded image that is still processed, in seconds.
+    #[cfg(feature = "preview-image")]
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "1",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub media_poll_interval: u64,
+    /// The multiplier of the polling interval after every poll, `1` keeps it constant.
+    #[cfg(feature = "preview-image")]
+    #[arg(long, value_name = "FACTOR", default_value = "1.5", value_parser = parse_backoff)]
+    pub media_poll_backoff: f64,
+    /// The maximum time to wait for an uploaded image to be processed, in seconds.
+    #[cfg(feature = "preview-image")]
+    #[arg(long, value_name = "SECONDS", default_value = "60")]
+    pub media_timeout: u64,
+    /// Do not post the item if the processing of its image times out.
+    /// Without this flag, the item is posted without the image.
+    #[cfg(feature = "preview-image")]
+    #[arg(long)]
+    pub drop_on_media_timeout: bool,
     /// Use Mastodon instead of Pleroma.
     #[arg(short = 'm', long = "mastodon")]
     pub is_mastodon: bool,
@@ -38,49 +237,236 @@ pub struct Cli {
     /// Do not post anything, will print the items that would be posted.
     #[arg(short, long)]
     pub dry_run: bool,
+    /// The address of the health and status server, e.g. `127.0.0.1:9000`.
+    #[arg(short, long, value_name = "ADDR")]
+    pub listen: Option<std::net::SocketAddr>,
+}
+
+/// The arguments of the `check-feeds` subcommand.
+#[derive(Debug, Args)]
+pub struct CheckFeedsArgs {
+    /// The file that contains the feeds.
+    #[arg(short, long, value_name = "PATH")]
+    pub feeds_file: PathBuf,
+}
+
+/// The arguments of the `list` subcommand.
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// The file that contains the feeds.
+    #[arg(short, long, value_name = "PATH")]
+    pub feeds_file: PathBuf,
+    /// The file that keeps the last seen state of the feeds.
+    #[arg(short = 'S', long, value_name = "PATH")]
+    pub state_file: Option<PathBuf>,
+}
+
+/// The arguments of the `import` subcommand.
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// The file to import the feeds from, a feeds file or an OPML file.
+    #[arg(value_name = "PATH")]
+    pub file: PathBuf,
+    /// The feeds file to import the feeds to, will be created if it does not exist.
+    #[arg(short, long, value_name = "PATH")]
+    pub feeds_file: PathBuf,
+}
+
+/// The arguments of the `export` subcommand.
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// The file that contains the feeds.
+    #[arg(short, long, value_name = "PATH")]
+    pub feeds_file: PathBuf,
+    /// The file to export the feeds to. Without it, the feeds are printed.
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+    /// The format of the exported feeds.
+    #[arg(long, value_name = "FORMAT", default_value = "text")]
+    pub format: FeedsFormat,
+}
+
+/// The format of the feeds file.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FeedsFormat {
+    /// One feed url per line, followed by the feed options.
+    Text,
+    /// OPML file.
+    Opml,
+}
+
+/// The arguments of the `login` subcommand.
+#[derive(Debug, Args)]
+pub struct LoginArgs {
+    /// The instance URL.
+    #[arg(short, long, value_name = "URL")]
+    pub instance: url::Url,
+    /// The config file to write the access token to, will be created if it does not exist.
+    #[arg(short, long, value_name = "PATH", default_value = "config.toml")]
+    pub config: PathBuf,
+    /// The account name in the config file.
+    #[arg(short, long, value_name = "NAME", default_value = "default")]
+    pub name: String,
+    /// The instance is a Mastodon instance.
+    #[arg(short = 'm', long = "mastodon")]
+    pub is_mastodon: bool,
+    /// Request the notifications scopes, they are needed to read the commands
+    /// of `--allow` and `--subscriptions-file`.
+    #[arg(long)]
+    pub commands: bool,
 }
 
 impl Cli {
+    /// Parses the CLI arguments.
+    /// The `run` subcommand is the default one, so `pleroma-rss -f feeds.txt` is
+    /// the same as `pleroma-rss run -f feeds.txt`.
+    pub fn parse_args() -> Self {
+        let mut args: Vec<OsString> = std::env::args_os().collect();
+        // Skip the global options, they can be before the subcommand.
+        let mut idx = 1;
+        while let Some(arg) = args.get(idx).and_then(|arg| arg.to_str()) {
+            match arg {
+                "--log-format" => idx += 2,
+                _ if arg.starts_with("--log-format=") => idx += 1,
+                _ => break,
+            }
+        }
+        let is_run_flag = args
+            .get(idx)
+            .and_then(|arg| arg.to_str())
+            .is_some_and(|arg| {
+                arg.starts_with('-') && !matches!(arg, "-h" | "--help" | "-V" | "--version")
+            });
+        if is_run_flag {
+            args.insert(idx, "run".into());
+        }
+        Self::parse_from(args)
+    }
+}
+
+impl RunArgs {
     /// Check the CLI arguments.
     pub fn check(&self) -> Presult<()> {
         utils::check_file("feeds file", &self.feeds_file)?;
+        if let Some(config) = &self.config {
+            utils::check_file("config file", config)?;
+        }
+        if let Some(access_token_file) = &self.access_token_file {
+            utils::check_file("access token file", access_token_file)?;
+        }
         #[cfg(feature = "preview-image")]
         {
-            utils::check_file("image teplate", &self.preview_image_template)?;
-            utils::check_file("default preview image", &self.default_preview_image)?;
+            if let Some(template) = &self.preview_image_template {
+                utils::check_file("image teplate", template)?;
+            }
+            #[cfg(feature = "native-preview")]
+            if let Some(layout) = &self.preview_layout {
+                utils::check_file("preview layout", layout)?;
+            }
+            if let Some(default_preview_image) = &self.default_preview_image {
+                utils::check_file("default preview image", default_preview_image)?;
+            }
         }
         Ok(())
     }
 }
 
 #[cfg(not(feature = "preview-image"))]
-impl std::fmt::Debug for Cli {
+impl std::fmt::Debug for RunArgs {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Cli")
-            .field("access_token", &"***")
+        f.debug_struct("RunArgs")
+            .field("config", &self.config)
+            .field("access_token", &self.access_token.as_ref().map(|_| "***"))
+            .field("access_token_file", &self.access_token_file)
             .field("feeds_file", &self.feeds_file)
+            .field("state_file", &self.state_file)
             .field("base_url", &self.base_url)
             .field("items_sleep", &self.items_sleep)
             .field("watting_new", &self.watting_new)
+            .field("max_failures", &self.max_failures)
+            .field("admin", &self.admin)
+            .field("admin_interval", &self.admin_interval)
+            .field("allow", &self.allow)
+            .field("subscriptions_file", &self.subscriptions_file)
+            .field("max_subscriptions", &self.max_subscriptions)
+            .field("link_previews", &self.link_previews)
+            .field("link_preview_timeout", &self.link_preview_timeout)
+            .field("link_preview_cache_ttl", &self.link_preview_cache_ttl)
+            .field("link_preview_interval", &self.link_preview_interval)
             .field("only_new", &self.only_new)
             .field("dry_run", &self.dry_run)
+            .field("listen", &self.listen)
             .finish()
     }
 }
 
 #[cfg(feature = "preview-image")]
-impl std::fmt::Debug for Cli {
+impl std::fmt::Debug for RunArgs {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Cli")
-            .field("access_token", &"***")
+        let mut debug = f.debug_struct("RunArgs");
+        debug
+            .field("config", &self.config)
+            .field("access_token", &self.access_token.as_ref().map(|_| "***"))
+            .field("access_token_file", &self.access_token_file)
             .field("feeds_file", &self.feeds_file)
+            .field("state_file", &self.state_file)
             .field("base_url", &self.base_url)
             .field("items_sleep", &self.items_sleep)
             .field("watting_new", &self.watting_new)
+            .field("max_failures", &self.max_failures)
+            .field("admin", &self.admin)
+            .field("admin_interval", &self.admin_interval)
+            .field("allow", &self.allow)
+            .field("subscriptions_file", &self.subscriptions_file)
+            .field("max_subscriptions", &self.max_subscriptions)
+            .field("link_previews", &self.link_previews)
+            .field("link_preview_timeout", &self.link_preview_timeout)
+            .field("link_preview_cache_ttl", &self.link_preview_cache_ttl)
+            .field("link_preview_interval", &self.link_preview_interval)
             .field("preview_image_template", &self.preview_image_template)
+            .field("preview_size", &self.preview_size)
+            .field("preview_font", &self.preview_font);
+        #[cfg(feature = "native-preview")]
+        debug.field("preview_layout", &self.preview_layout);
+        debug
             .field("default_preview_image", &self.default_preview_image)
+            .field("image_sources", &self.image_sources)
+            .field("attach_images", &self.attach_images)
+            .field("alt_text_template", &self.alt_text_template)
+            .field("max_alt_text_length", &self.max_alt_text_length)
+            .field("image_cache_dir", &self.image_cache_dir)
+            .field("image_cache_ttl", &self.image_cache_ttl)
+            .field("max_image_size", &self.max_image_size)
+            .field("image_timeout", &self.image_timeout)
+            .field("media_poll_interval", &self.media_poll_interval)
+            .field("media_poll_backoff", &self.media_poll_backoff)
+            .field("media_timeout", &self.media_timeout)
+            .field("drop_on_media_timeout", &self.drop_on_media_timeout)
             .field("only_new", &self.only_new)
             .field("dry_run", &self.dry_run)
+            .field("listen", &self.listen)
             .finish()
     }
 }
+
+/// Parses a size written as `WIDTHxHEIGHT`, e.g. `1200x630`.
+#[cfg(feature = "preview-image")]
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid size `{size}`, expected `WIDTHxHEIGHT`");
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses the media polling backoff, it can not be less than `1`.
+#[cfg(feature = "preview-image")]
+fn parse_backoff(backoff: &str) -> Result<f64, String> {
+    match backoff.parse::<f64>() {
+        Ok(backoff) if backoff >= 1.0 && backoff.is_finite() => Ok(backoff),
+        _ => Err(format!(
+            "invalid backoff `{backoff}`, expected a number from 1"
+        )),
+    }
+}
@@ -0,0 +1,283 @@
+use chrono::{DateTime, FixedOffset, TimeZone};
+
+use crate::{
+    cli::{CheckFeedsArgs, ExportArgs, FeedsFormat, ImportArgs, ListArgs, RunArgs},
+    config::Config,
+    errors::{Error as PError, Result as PResult},
+    opml,
+    state::State,
+    utils,
+};
+
+/// Validates the config and the feeds file, and checks the access token of every account.
+pub async fn check(args: RunArgs) -> PResult<()> {
+    args.check()?;
+    let config = Config::new(&args)?;
+    println!(
+        "[ok] The config is valid. {} account(s), {} feed(s).",
+        config.accounts.len(),
+        config.feeds.len()
+    );
+    let mut problems = 0;
+    for account in &config.accounts {
+        match account.client().verify_account_credentials().await {
+            Ok(res) => println!("[ok] {}: logged in as @{}", account.name, res.json().acct),
+            Err(err) => {
+                problems += 1;
+                println!("[error] {}: {}", account.name, err);
+            }
+        }
+    }
+    if problems > 0 {
+        return Err(PError::CheckFailed(problems));
+    }
+    Ok(())
+}
+
+/// The diagnostic report of a feed.
+#[derive(Debug, Default)]
+struct FeedReport {
+    /// The HTTP status of the feed response.
+    status: Option<reqwest::StatusCode>,
+    /// The detected format of the feed.
+    format: &'static str,
+    /// The number of items in the feed.
+    items: usize,
+    /// The number of items that the bot can post, the items without any problem.
+    postable: usize,
+    /// The number of items without a publish date.
+    missing_dates: usize,
+    /// The number of items with an invalid publish date.
+    invalid_dates: usize,
+    /// The number of items without a title.
+    missing_titles: usize,
+    /// The number of items without a link.
+    missing_links: usize,
+    /// The number of items without a description.
+    missing_descriptions: usize,
+    /// The publish date of the newest item.
+    newest: Option<DateTime<FixedOffset>>,
+    /// The error that makes the feed unusable.
+    error: Option<String>,
+}
+
+impl FeedReport {
+    /// Fetches the feed and creates its report.
+    async fn new(url: &url::Url) -> Self {
+        let mut report = Self {
+            format: "unknown",
+            ..Default::default()
+        };
+        let response = match reqwest::get(url.as_str()).await {
+            Ok(response) => response,
+            Err(err) => {
+                report.error = Some(err.to_string());
+                return report;
+            }
+        };
+        report.status = Some(response.status());
+        if !response.status().is_success() {
+            report.error = Some(format!("The server responded with {}", response.status()));
+            return report;
+        }
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(err) => {
+                report.error = Some(err.to_string());
+                return report;
+            }
+        };
+        let channel = match body.parse::<rss::Channel>() {
+            Ok(channel) => channel,
+            Err(err) => {
+                report.format = detect_format(&body);
+                report.error = Some(err.to_string());
+                return report;
+            }
+        };
+        report.format = "RSS";
+        report.items = channel.items.len();
+        for item in &channel.items {
+            let mut is_postable = true;
+            match item.pub_date().map(DateTime::parse_from_rfc2822) {
+                Some(Ok(date)) => report.newest = report.newest.max(Some(date)),
+                Some(Err(_)) => {
+                    report.invalid_dates += 1;
+                    is_postable = false;
+                }
+                None => {
+                    report.missing_dates += 1;
+                    is_postable = false;
+                }
+            }
+            let missing = [
+                (&mut report.missing_titles, item.title().is_none()),
+                (&mut report.missing_links, item.link().is_none()),
+                (
+                    &mut report.missing_descriptions,
+                    item.description().is_none(),
+                ),
+            ];
+            for (count, is_missing) in missing {
+                *count += usize::from(is_missing);
+                is_postable &= !is_missing;
+            }
+            report.postable += usize::from(is_postable);
+        }
+        report
+    }
+
+    /// Returns `true` if the bot can post the feed items, the feed is fetched and parsed
+    /// and has postable items. The invalid items are skipped by the bot.
+    fn is_usable(&self) -> bool {
+        self.error.is_none() && self.postable > 0
+    }
+
+    /// Returns `true` if some items of the feed are skipped by the bot.
+    fn has_warnings(&self) -> bool {
+        self.postable < self.items
+    }
+}
+
+impl std::fmt::Display for FeedReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = self
+            .status
+            .map_or_else(|| "no response".to_owned(), |status| status.to_string());
+        writeln!(f, "  HTTP status: {status}")?;
+        writeln!(f, "  Format: {}", self.format)?;
+        if let Some(err) = &self.error {
+            return writeln!(f, "  Error: {err}");
+        }
+        writeln!(f, "  Items: {}", self.items)?;
+        writeln!(f, "  Postable items: {}", self.postable)?;
+        let problems = [
+            (self.missing_dates, "without a publish date"),
+            (self.invalid_dates, "with an invalid publish date"),
+            (self.missing_titles, "without a title"),
+            (self.missing_links, "without a link"),
+            (self.missing_descriptions, "without a description"),
+        ];
+        for (count, problem) in problems.into_iter().filter(|(count, _)| count > &0) {
+            writeln!(f, "  Warning: Items {problem}: {count}")?;
+        }
+        let newest = self
+            .newest
+            .map_or_else(|| "unknown".to_owned(), |date| date.to_rfc2822());
+        writeln!(f, "  Newest item: {newest}")
+    }
+}
+
+/// Detects the format of a feed that is not a valid RSS feed.
+fn detect_format(body: &str) -> &'static str {
+    if body.contains("<feed") {
+        "Atom (not supported)"
+    } else if body.contains("<rdf:RDF") {
+        "RSS 1.0 (not supported)"
+    } else if body.contains("<rss") {
+        "RSS"
+    } else {
+        "unknown"
+    }
+}
+
+/// Fetches every feed in the feeds file and prints its report.
+/// Returns an error if any feed is unusable.
+pub async fn check_feeds(args: CheckFeedsArgs) -> PResult<()> {
+    utils::check_file("feeds file", &args.feeds_file)?;
+    let mut unusable = 0;
+    for feed in utils::parse_feeds(&args.feeds_file, false)? {
+        tracing::info!("Checking feed: {}", feed.url);
+        let report = FeedReport::new(&feed.url).await;
+        let status = if !report.is_usable() {
+            unusable += 1;
+            "unusable"
+        } else if report.has_warnings() {
+            "warning"
+        } else {
+            "ok"
+        };
+        println!("[{status}] {}\n{report}", feed.url);
+    }
+    if unusable > 0 {
+        return Err(PError::UnusableFeeds(unusable));
+    }
+    Ok(())
+}
+
+/// Prints the feeds with their accounts and last seen item.
+pub fn list(args: ListArgs) -> PResult<()> {
+    utils::check_file("feeds file", &args.feeds_file)?;
+    let state = match &args.state_file {
+        Some(state_file) => State::read(state_file)?,
+        None => State::default(),
+    };
+    for feed in utils::parse_feeds(&args.feeds_file, false)? {
+        let feed_state = state.feed(&feed).cloned().unwrap_or_default();
+        let last_post = feed_state
+            .last_post
+            .and_then(|last_post| chrono::Utc.timestamp_opt(last_post as i64, 0).single())
+            .map_or_else(|| "never".to_owned(), |date| date.to_rfc2822());
+        let accounts = if feed.accounts.is_empty() {
+            "all".to_owned()
+        } else {
+            feed.accounts.join(", ")
+        };
+        println!("{}", feed.url);
+        if let Some(title) = &feed.title {
+            println!("  Title: {title}");
+        }
+        if !feed.tags.is_empty() {
+            println!("  Tags: {}", feed.tags.join(", "));
+        }
+        println!("  Accounts: {accounts}\n  Last post: {last_post}");
+        if let Some(moved_to) = &feed_state.moved_to {
+            println!("  Moved to: {moved_to}");
+        }
+        if feed_state.disabled {
+            println!("  Disabled after {} failure(s)", feed_state.failures);
+        } else if feed_state.failures > 0 {
+            println!("  Failures: {}", feed_state.failures);
+        }
+    }
+    Ok(())
+}
+
+/// Adds the feeds of the given file to the feeds file, skipping the existing feeds.
+pub fn import(args: ImportArgs) -> PResult<()> {
+    utils::check_file("import file", &args.file)?;
+    let mut feeds = if args.feeds_file.exists() {
+        utils::parse_feeds(&args.feeds_file, false)?
+    } else {
+        Vec::new()
+    };
+    let mut imported = 0;
+    for feed in utils::parse_feeds(&args.file, false)? {
+        if !feeds.iter().any(|f| f.url == feed.url) {
+            tracing::debug!("Importing feed: {}", feed.url);
+            feeds.push(feed);
+            imported += 1;
+        }
+    }
+    utils::write_feeds(&args.feeds_file, &feeds)?;
+    println!(
+        "Imported {imported} feed(s) to {}",
+        args.feeds_file.display()
+    );
+    Ok(())
+}
+
+/// Writes the feeds to the output file, or prints them.
+pub fn export(args: ExportArgs) -> PResult<()> {
+    utils::check_file("feeds file", &args.feeds_file)?;
+    let feeds = utils::parse_feeds(&args.feeds_file, false)?;
+    let content = match args.format {
+        FeedsFormat::Text => feeds.iter().map(|feed| format!("{feed}\n")).collect(),
+        FeedsFormat::Opml => opml::write(&feeds),
+    };
+    match &args.output {
+        Some(output) => std::fs::write(output, content)?,
+        None => print!("{content}"),
+    }
+    Ok(())
+}
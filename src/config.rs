@@ -1,61 +1,445 @@
-use megalodon::SNS;
+use megalodon::{Megalodon, SNS};
+use serde::{Deserialize, Serialize};
 
-use crate::{bot::Feed, cli::Cli, errors::Result as PResult, utils};
 #[cfg(feature = "preview-image")]
+use crate::cli::ImageSource;
+use crate::{
+    bot::{Feed, LinkPreviewOptions},
+    cli::RunArgs,
+    errors::{Error as PError, Result as PResult},
+    state::State,
+    utils,
+};
 use std::path::PathBuf;
 
+/// The name of the account that is created from the CLI arguments.
+pub const DEFAULT_ACCOUNT: &str = "default";
+/// The environment variable that contains the access token of the `default` account.
+pub const TOKEN_ENV: &str = "PLEROMA_RSS_TOKEN";
+
+/// The platform of the account instance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    /// Pleroma instance.
+    #[default]
+    Pleroma,
+    /// Mastodon instance.
+    Mastodon,
+}
+
+/// A bot account, the feeds items will be posted to it.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Account {
+    /// The account name, used to route the feeds to it.
+    pub name: String,
+    /// Base url of the account instance.
+    pub base_url: url::Url,
+    /// The account access token.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub access_token: String,
+    /// The file that contains the account access token, overrides `access_token`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token_file: Option<PathBuf>,
+    /// The platform of the instance.
+    #[serde(default)]
+    pub platform: Platform,
+    /// The sleep time between each item in seconds, overrides the `--items-sleep` flag.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items_sleep: Option<u64>,
+}
+
+/// The config file, contains the bot accounts.
+/// ### File format
+/// ```toml
+/// [[accounts]]
+/// name = "tech"
+/// base_url = "https://example.com"
+/// access_token_file = "/run/secrets/tech-token" # Or `access_token = "TOKEN"`
+/// platform = "mastodon" # Optional, default is `pleroma`
+/// items_sleep = 5 # Optional, default is `--items-sleep`
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ConfigFile {
+    /// The bot accounts.
+    #[serde(default)]
+    pub accounts: Vec<Account>,
+}
+
 /// The bot configuration.
 #[derive(Debug, Clone)]
 pub struct Config {
-    /// The bot token.
-    pub bot_token: String,
-    /// Base url of Pleroma instance.
-    pub base_url: url::Url,
-    /// The sleep time between each feed in seconds.
-    pub items_sleep: u64,
+    /// The bot accounts.
+    pub accounts: Vec<Account>,
     /// The sleep time after end all feeds (wait for new items) in seconds.
     pub watting_new: u64,
+    /// The number of consecutive failures before disabling a feed.
+    pub max_failures: u32,
+    /// The account that is notified about the problems of the bot, e.g. `admin@example.com`.
+    pub admin: Option<String>,
+    /// The minimum time between the admin notifications in seconds.
+    pub admin_interval: u64,
+    /// The accounts that can send commands to the bot by mentioning it.
+    pub allowed: Vec<String>,
+    /// The file of the users subscriptions, the subscriptions are disabled without it.
+    pub subscriptions_file: Option<PathBuf>,
+    /// The maximum number of subscriptions of a user.
+    pub max_subscriptions: usize,
+    /// The link preview options, the missing fields of the items are filled from their pages.
+    pub link_preview: LinkPreviewOptions,
     /// Rss feeds file path.
     pub feeds: Vec<Feed>,
-    /// Only new flag.
-    pub only_new: bool,
+    /// The feeds file.
+    pub feeds_file: PathBuf,
+    /// The file that keeps the last seen state of the feeds.
+    pub state_file: Option<PathBuf>,
     /// Dry run flag.
     pub dry_run: bool,
-    /// Use Mastodon instead of Pleroma.
-    pub is_mastodon: bool,
     /// The preview image html template.
     #[cfg(feature = "preview-image")]
-    pub preview_image_template: PathBuf,
+    pub preview_image_template: Option<PathBuf>,
+    /// The size of the SVG preview image, the template size if not set.
+    #[cfg(feature = "preview-image")]
+    pub preview_size: Option<(u32, u32)>,
+    /// The font files or directories of the SVG preview image.
+    #[cfg(feature = "preview-image")]
+    pub preview_fonts: Vec<PathBuf>,
+    /// The preview image layout, the preview is rendered natively with it.
+    #[cfg(feature = "native-preview")]
+    pub preview_layout: Option<PathBuf>,
     /// The default image of the preview image.
     #[cfg(feature = "preview-image")]
-    pub default_preview_image: PathBuf,
+    pub default_preview_image: Option<PathBuf>,
+    /// The sources of the item images, in the order they are tried.
+    #[cfg(feature = "preview-image")]
+    pub image_sources: Vec<ImageSource>,
+    /// Attach the item image to the post instead of the preview image.
+    #[cfg(feature = "preview-image")]
+    pub attach_images: bool,
+    /// The alt text template of the uploaded images.
+    #[cfg(feature = "preview-image")]
+    pub alt_text_template: String,
+    /// The maximum length of the alt text in characters.
+    #[cfg(feature = "preview-image")]
+    pub max_alt_text_length: usize,
+    /// The directory of the downloaded item images.
+    #[cfg(feature = "preview-image")]
+    pub image_cache_dir: PathBuf,
+    /// The time to keep the downloaded item images in seconds.
+    #[cfg(feature = "preview-image")]
+    pub image_cache_ttl: u64,
+    /// The maximum size of an item image in bytes.
+    #[cfg(feature = "preview-image")]
+    pub max_image_size: u64,
+    /// The maximum time to download an item image in seconds.
+    #[cfg(feature = "preview-image")]
+    pub image_timeout: u64,
+    /// The time between the polls of a processed image in seconds.
+    #[cfg(feature = "preview-image")]
+    pub media_poll_interval: u64,
+    /// The multiplier of the polling interval after every poll.
+    #[cfg(feature = "preview-image")]
+    pub media_poll_backoff: f64,
+    /// The maximum time to wait for a processed image in seconds.
+    #[cfg(feature = "preview-image")]
+    pub media_timeout: u64,
+    /// Do not post the item if the processing of its image times out.
+    #[cfg(feature = "preview-image")]
+    pub drop_on_media_timeout: bool,
+}
+
+impl Platform {
+    /// Reutnrs the SNS (Pleroma or Mastodon)
+    pub fn sns(self) -> SNS {
+        match self {
+            Platform::Pleroma => SNS::Pleroma,
+            Platform::Mastodon => SNS::Mastodon,
+        }
+    }
+}
+
+impl Account {
+    /// Reutnrs the SNS (Pleroma or Mastodon)
+    pub fn sns(&self) -> SNS {
+        self.platform.sns()
+    }
+
+    /// Reads the access token from the `access_token_file` if there is one.
+    /// Returns an error if the account has no access token.
+    pub fn load_token(&mut self) -> PResult<()> {
+        if let Some(file) = &self.access_token_file {
+            tracing::debug!(
+                "Reading the access token of the account {} from: {}",
+                self.name,
+                file.display()
+            );
+            self.access_token = std::fs::read_to_string(file)?.trim().to_owned();
+        }
+        if self.access_token.is_empty() {
+            return Err(PError::NoAccessToken(self.name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Checks the access token of the account with the instance.
+    /// A rejected token (`401` or `403`) is returned as the fatal [`PError::Unauthorized`].
+    pub async fn verify_token(&self, client: &(dyn Megalodon + Send + Sync)) -> PResult<()> {
+        match client.verify_account_credentials().await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                let err = PError::from(err);
+                match (err.instance_status(), err) {
+                    (Some(401 | 403), PError::Megalodon(err)) => {
+                        Err(PError::Unauthorized(self.name.clone(), err))
+                    }
+                    (_, err) => Err(err),
+                }
+            }
+        }
+    }
+
+    /// Creates a new client for the account instance.
+    pub fn client(&self) -> Box<dyn Megalodon + Send + Sync> {
+        megalodon::generator(
+            self.sns(),
+            self.base_url.as_str().trim_end_matches('/').to_owned(),
+            Some(self.access_token.clone()),
+            None,
+        )
+    }
+}
+
+impl std::fmt::Debug for Account {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Account")
+            .field("name", &self.name)
+            .field("base_url", &self.base_url)
+            .field("access_token", &"***")
+            .field("access_token_file", &self.access_token_file)
+            .field("platform", &self.platform)
+            .field("items_sleep", &self.items_sleep)
+            .finish()
+    }
+}
+
+impl ConfigFile {
+    /// Reads the config file.
+    pub fn read(path: &std::path::Path) -> PResult<Self> {
+        tracing::debug!("Reading config file: {}", path.display());
+        toml::from_str(&std::fs::read_to_string(path)?).map_err(From::from)
+    }
+
+    /// Writes the config file, it is only readable by the bot user because of the access tokens.
+    pub fn write(&self, path: &std::path::Path) -> PResult<()> {
+        tracing::debug!("Writing config file: {}", path.display());
+        utils::write_atomic(path, toml::to_string(self)?)
+    }
+
+    /// Adds the account to the config file, replacing the account with the same name.
+    pub fn set_account(&mut self, account: Account) {
+        if let Some(old) = self.accounts.iter_mut().find(|a| a.name == account.name) {
+            *old = account;
+        } else {
+            self.accounts.push(account);
+        }
+    }
 }
 
 impl Config {
     /// Creates a new config.
-    pub fn new(cli: &Cli) -> PResult<Self> {
+    pub fn new(cli: &RunArgs) -> PResult<Self> {
+        Self::load(cli, cli.only_new)
+    }
+
+    /// Reloads the feeds file and the config file, keeping the state of the unchanged feeds.
+    /// The added feeds will only post the new items.
+    pub fn reload(&mut self, cli: &RunArgs) -> PResult<()> {
+        tracing::info!("Reloading the config.");
+        let mut config = Self::load(cli, true)?;
+        for feed in &mut config.feeds {
+            if let Some(old) = self.feeds.iter().find(|old| old.url == feed.url) {
+                feed.moved_to = old.moved_to.clone();
+                feed.last_post = old.last_post;
+                feed.failures = old.failures;
+                feed.outages = old.outages;
+                feed.disabled = old.disabled;
+                feed.retry_at = old.retry_at;
+            } else {
+                tracing::info!("New feed added: {}", feed.url);
+            }
+        }
+        for old in &self.feeds {
+            if !config.feeds.iter().any(|feed| feed.url == old.url) {
+                tracing::info!("Feed removed: {}", old.url);
+            }
+        }
+        *self = config;
+        Ok(())
+    }
+
+    /// Loads the config, `only_new` is used for the feeds that are not in the state file.
+    fn load(cli: &RunArgs, only_new: bool) -> PResult<Self> {
+        let accounts = Self::accounts(cli)?;
+        let mut feeds = utils::parse_feeds(&cli.feeds_file, only_new)?;
+        if let Some(state_file) = &cli.state_file {
+            State::read(state_file)?.apply(&mut feeds);
+        }
+        // Make sure that every feed is routed to an existing account.
+        for feed in &feeds {
+            if let Some(name) = feed
+                .accounts
+                .iter()
+                .find(|name| !accounts.iter().any(|account| &account.name == *name))
+            {
+                return Err(PError::UnknownAccount(name.clone(), feed.url.clone()));
+            }
+        }
         Ok(Self {
-            bot_token: cli.access_token.clone(),
-            base_url: cli.base_url.clone(),
-            items_sleep: cli.items_sleep,
+            accounts,
             watting_new: cli.watting_new,
-            feeds: utils::parse_feeds(&cli.feeds_file, cli.only_new)?,
-            only_new: cli.only_new,
+            max_failures: cli.max_failures,
+            admin: cli.admin.clone(),
+            admin_interval: cli.admin_interval,
+            allowed: cli.allow.clone(),
+            subscriptions_file: cli.subscriptions_file.clone(),
+            max_subscriptions: cli.max_subscriptions,
+            link_preview: LinkPreviewOptions {
+                enabled: cli.link_previews,
+                timeout: cli.link_preview_timeout,
+                cache_ttl: cli.link_preview_cache_ttl,
+                interval: cli.link_preview_interval,
+            },
+            feeds,
+            feeds_file: cli.feeds_file.clone(),
+            state_file: cli.state_file.clone(),
             dry_run: cli.dry_run,
-            is_mastodon: cli.is_mastodon,
             #[cfg(feature = "preview-image")]
             preview_image_template: cli.preview_image_template.clone(),
             #[cfg(feature = "preview-image")]
+            preview_size: cli.preview_size,
+            #[cfg(feature = "preview-image")]
+            preview_fonts: cli.preview_font.clone(),
+            #[cfg(feature = "native-preview")]
+            preview_layout: cli.preview_layout.clone(),
+            #[cfg(feature = "preview-image")]
             default_preview_image: cli.default_preview_image.clone(),
+            #[cfg(feature = "preview-image")]
+            image_sources: cli.image_sources.clone(),
+            #[cfg(feature = "preview-image")]
+            attach_images: cli.attach_images,
+            #[cfg(feature = "preview-image")]
+            alt_text_template: cli.alt_text_template.clone(),
+            #[cfg(feature = "preview-image")]
+            max_alt_text_length: cli.max_alt_text_length,
+            #[cfg(feature = "preview-image")]
+            image_cache_dir: cli
+                .image_cache_dir
+                .clone()
+                .unwrap_or_else(default_image_cache_dir),
+            #[cfg(feature = "preview-image")]
+            image_cache_ttl: cli.image_cache_ttl,
+            #[cfg(feature = "preview-image")]
+            max_image_size: cli.max_image_size,
+            #[cfg(feature = "preview-image")]
+            image_timeout: cli.image_timeout,
+            #[cfg(feature = "preview-image")]
+            media_poll_interval: cli.media_poll_interval,
+            #[cfg(feature = "preview-image")]
+            media_poll_backoff: cli.media_poll_backoff,
+            #[cfg(feature = "preview-image")]
+            media_timeout: cli.media_timeout,
+            #[cfg(feature = "preview-image")]
+            drop_on_media_timeout: cli.drop_on_media_timeout,
         })
     }
 
-    /// Reutnrs the SNS (Pleroma or Mastodon)
-    pub fn sns(&self) -> SNS {
-        if self.is_mastodon {
-            SNS::Mastodon
+    /// Saves the last seen state of the feeds, if there is a state file.
+    pub fn save_state(&self) -> PResult<()> {
+        if let Some(state_file) = &self.state_file {
+            State::from_feeds(&self.feeds).write(state_file)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the accounts of the bot, from the config file and the CLI arguments.
+    fn accounts(cli: &RunArgs) -> PResult<Vec<Account>> {
+        let mut accounts = if let Some(config_file) = &cli.config {
+            ConfigFile::read(config_file)?.accounts
         } else {
-            SNS::Pleroma
+            Vec::new()
+        };
+        if let Some(base_url) = &cli.base_url {
+            accounts.push(Account {
+                name: DEFAULT_ACCOUNT.to_owned(),
+                base_url: base_url.clone(),
+                access_token: Self::default_token(cli)?,
+                access_token_file: None,
+                platform: if cli.is_mastodon {
+                    Platform::Mastodon
+                } else {
+                    Platform::Pleroma
+                },
+                items_sleep: None,
+            });
+        }
+        if accounts.is_empty() {
+            return Err(PError::NoAccounts);
         }
+        for (idx, account) in accounts.iter().enumerate() {
+            if accounts[..idx].iter().any(|a| a.name == account.name) {
+                return Err(PError::DuplicateAccount(account.name.clone()));
+            }
+        }
+        for account in &mut accounts {
+            account.load_token()?;
+            account.items_sleep.get_or_insert(cli.items_sleep);
+        }
+        Ok(accounts)
+    }
+
+    /// Returns the access token of the `default` account.
+    /// The token is taken from the `PLEROMA_RSS_TOKEN` environment variable, then the
+    /// `--access-token-file` file, then the `--access-token` flag as a last resort.
+    fn default_token(cli: &RunArgs) -> PResult<String> {
+        if let Some(token) = std::env::var(TOKEN_ENV).ok().filter(|t| !t.is_empty()) {
+            tracing::debug!("Using the access token from the {} variable.", TOKEN_ENV);
+            return Ok(token);
+        }
+        if let Some(file) = &cli.access_token_file {
+            tracing::debug!("Using the access token from: {}", file.display());
+            return Ok(std::fs::read_to_string(file)?.trim().to_owned());
+        }
+        if let Some(token) = &cli.access_token {
+            tracing::warn!(
+                "The access token is passed as an argument, it can be seen in the shell history \
+                 and the process list. Use the {} variable or --access-token-file instead.",
+                TOKEN_ENV
+            );
+            return Ok(token.clone());
+        }
+        Err(PError::NoAccessToken(DEFAULT_ACCOUNT.to_owned()))
+    }
+}
+
+/// Returns the default image cache directory, `pleroma-rss/images` in the user cache
+/// directory (`$XDG_CACHE_HOME` or `~/.cache`). Without them, it is a directory of the
+/// user in the temporary directory.
+#[cfg(feature = "preview-image")]
+fn default_image_cache_dir() -> PathBuf {
+    let absolute = |var| {
+        std::env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+    };
+    if let Some(cache) =
+        absolute("XDG_CACHE_HOME").or_else(|| absolute("HOME").map(|home| home.join(".cache")))
+    {
+        return cache.join("pleroma-rss").join("images");
     }
+    #[cfg(unix)]
+    // SAFETY: `geteuid` has no preconditions and never fails.
+    let user = unsafe { libc::geteuid() }.to_string();
+    #[cfg(not(unix))]
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    std::env::temp_dir().join(format!("pleroma-rss-images-{user}"))
 }
@@ -22,9 +22,18 @@ pub enum Error {
     NoLink(url::Url),
     #[error("There is no description in items of the feed `{0}`")]
     NoDescription(url::Url),
+    #[error("The feed `{0}` returned the HTTP status {1}")]
+    FeedStatus(url::Url, u16),
+    #[error("The feed `{0}` has too many redirects")]
+    TooManyRedirects(url::Url),
+    #[error("The url `{0}` is not a public http(s) url")]
+    NotPublic(url::Url),
     #[error("The image takes too long to upload: {0}")]
     #[cfg(feature = "preview-image")]
     ImageTimeout(String),
+    #[error("Preview image error: {0}")]
+    #[cfg(feature = "preview-image")]
+    Preview(String),
     #[error("Invalid feed URL: {0}")]
     InvalidUrl(#[from] url::ParseError),
     #[error("Request error: {0}")]
@@ -34,7 +43,242 @@ pub enum Error {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("Megalodon error: {0}")]
-    Megalodon(#[from] megalodon::error::Error),
+    Megalodon(Box<megalodon::error::Error>),
+    #[error("The access token of the account `{0}` is rejected: {1}")]
+    /// First argument is the account name, second argument is the error of the credentials check.
+    Unauthorized(String, Box<megalodon::error::Error>),
+    #[error("Config file error: {0}")]
+    Config(#[from] toml::de::Error),
+    #[error("Config file error: {0}")]
+    ConfigWrite(#[from] toml::ser::Error),
+    #[error("Status server error: {0}")]
+    Server(#[from] hyper::Error),
+    #[error("OPML error: {0}")]
+    Opml(#[from] quick_xml::Error),
+    #[error("State file error: {0}")]
+    State(#[from] serde_json::Error),
+    #[error("The instance did not return an authorization URL")]
+    NoAuthorizationUrl,
+    #[error("There is no account to post to, use `--config` or `--base-url` and `--access-token`")]
+    NoAccounts,
+    #[error("The check found {0} problem(s)")]
+    CheckFailed(usize),
+    #[error("{0} feed(s) are unusable")]
+    UnusableFeeds(usize),
+    #[error("The feeds file `{0}` is an OPML file, it is not modified to keep its outlines")]
+    OpmlFeedsFile(String),
+    #[error(
+        "The `once` subcommand needs `--state-file`, otherwise every run posts the same items"
+    )]
+    NoStateFile,
+    #[error("There is no access token for the account `{0}`")]
+    NoAccessToken(String),
+    #[error("The account `{0}` is defined more than once")]
+    DuplicateAccount(String),
+    #[error("The feed `{1}` is routed to the account `{0}`, but there is no such account")]
+    UnknownAccount(String, url::Url),
+    #[error("Invalid feed option `{0}` for the feed `{1}`")]
+    InvalidFeedOption(String, url::Url),
+}
+
+/// The scope of an error, decides what the bot does when it happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The error is limited to one feed item, the item is skipped.
+    Item,
+    /// The error is limited to one feed, the feed is skipped until the next poll.
+    Feed,
+    /// The error may go away by itself, e.g. a network error. It is reported and retried later.
+    Transient,
+    /// A configuration or authentication error, the bot stops.
+    Fatal,
+}
+
+impl Error {
+    /// Returns the class of the error.
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::NoPublishDate(_)
+            | Self::InvalidPublishDate(_)
+            | Self::NoTitle(_)
+            | Self::NoLink(_)
+            | Self::NoDescription(_) => ErrorClass::Item,
+            #[cfg(feature = "preview-image")]
+            Self::ImageTimeout(_) | Self::Preview(_) => ErrorClass::Item,
+            Self::Rss(_)
+            | Self::FeedStatus(..)
+            | Self::TooManyRedirects(_)
+            | Self::NotPublic(_) => ErrorClass::Feed,
+            Self::Request(_) | Self::Io(_) | Self::State(_) => ErrorClass::Transient,
+            Self::Megalodon(_) => instance_class(self.instance_status()),
+            Self::NotFound(..)
+            | Self::NotAFile(..)
+            | Self::NotReadable(..)
+            | Self::EmptyFile(..)
+            | Self::InvalidUrl(_)
+            | Self::Config(_)
+            | Self::ConfigWrite(_)
+            | Self::Server(_)
+            | Self::Opml(_)
+            | Self::NoAuthorizationUrl
+            | Self::NoAccounts
+            | Self::CheckFailed(_)
+            | Self::UnusableFeeds(_)
+            | Self::NoStateFile
+            | Self::OpmlFeedsFile(_)
+            | Self::NoAccessToken(_)
+            | Self::DuplicateAccount(_)
+            | Self::UnknownAccount(..)
+            | Self::InvalidFeedOption(..)
+            | Self::Unauthorized(..) => ErrorClass::Fatal,
+        }
+    }
+
+    /// Returns the HTTP status of an instance error.
+    pub fn instance_status(&self) -> Option<u16> {
+        match self {
+            Self::Megalodon(err) | Self::Unauthorized(_, err) => match err.as_ref() {
+                megalodon::error::Error::OwnError(err) => err.status,
+                megalodon::error::Error::RequestError(err) => {
+                    err.status().map(|status| status.as_u16())
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the bot should stop because of the error.
+    pub fn is_fatal(&self) -> bool {
+        self.class() == ErrorClass::Fatal
+    }
+
+    /// Returns the name of the error kind, used as a metrics label.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Self::NotFound(..) => "not_found",
+            Self::NotAFile(..) => "not_a_file",
+            Self::NotReadable(..) => "not_readable",
+            Self::EmptyFile(..) => "empty_file",
+            Self::NoPublishDate(_) => "no_publish_date",
+            Self::InvalidPublishDate(_) => "invalid_publish_date",
+            Self::NoTitle(_) => "no_title",
+            Self::NoLink(_) => "no_link",
+            Self::NoDescription(_) => "no_description",
+            Self::FeedStatus(..) => "feed_status",
+            Self::TooManyRedirects(_) => "too_many_redirects",
+            Self::NotPublic(_) => "not_public",
+            #[cfg(feature = "preview-image")]
+            Self::ImageTimeout(_) => "image_timeout",
+            #[cfg(feature = "preview-image")]
+            Self::Preview(_) => "preview",
+            Self::InvalidUrl(_) => "invalid_url",
+            Self::Request(_) => "request",
+            Self::Rss(_) => "rss",
+            Self::Io(_) => "io",
+            Self::Megalodon(_) => "megalodon",
+            Self::Unauthorized(..) => "unauthorized",
+            Self::Config(_) | Self::ConfigWrite(_) => "config",
+            Self::Server(_) => "server",
+            Self::Opml(_) => "opml",
+            Self::State(_) => "state",
+            Self::NoAuthorizationUrl => "no_authorization_url",
+            Self::NoAccounts => "no_accounts",
+            Self::CheckFailed(_) | Self::UnusableFeeds(_) => "check_failed",
+            Self::NoStateFile => "no_state_file",
+            Self::OpmlFeedsFile(_) => "opml_feeds_file",
+            Self::NoAccessToken(_) => "no_access_token",
+            Self::DuplicateAccount(_) => "duplicate_account",
+            Self::UnknownAccount(..) => "unknown_account",
+            Self::InvalidFeedOption(..) => "invalid_feed_option",
+        }
+    }
+}
+
+impl From<megalodon::error::Error> for Error {
+    fn from(err: megalodon::error::Error) -> Self {
+        Self::Megalodon(Box::new(err))
+    }
+}
+
+/// Returns the class of an instance error by its HTTP status.
+/// A rejected request (e.g. a status or a media) is an item error, and anything else
+/// (rate limit, server and network errors) is transient. A `401` or `403` may be about
+/// the request only, a rejected access token is found by [`Account::verify_token`].
+///
+/// [`Account::verify_token`]: crate::config::Account::verify_token
+fn instance_class(status: Option<u16>) -> ErrorClass {
+    match status {
+        Some(429) => ErrorClass::Transient,
+        Some(400..=499) => ErrorClass::Item,
+        _ => ErrorClass::Transient,
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use megalodon::error::Kind;
+
+    fn feed_url() -> url::Url {
+        url::Url::parse("https://example.com/feed.xml").unwrap()
+    }
+
+    fn instance_error(status: u16) -> Error {
+        megalodon::error::Error::new_own(
+            "error".to_owned(),
+            Kind::HTTPStatusError,
+            None,
+            Some(status),
+        )
+        .into()
+    }
+
+    #[test]
+    fn item_errors() {
+        assert_eq!(Error::NoPublishDate(feed_url()).class(), ErrorClass::Item);
+        assert_eq!(
+            Error::InvalidPublishDate(feed_url()).class(),
+            ErrorClass::Item
+        );
+        assert_eq!(Error::NoTitle(feed_url()).class(), ErrorClass::Item);
+        assert_eq!(Error::NoLink(feed_url()).class(), ErrorClass::Item);
+        assert_eq!(Error::NoDescription(feed_url()).class(), ErrorClass::Item);
+        assert_eq!(instance_error(422).class(), ErrorClass::Item);
+        assert_eq!(instance_error(401).class(), ErrorClass::Item);
+        assert_eq!(instance_error(403).class(), ErrorClass::Item);
+    }
+
+    #[test]
+    fn feed_errors() {
+        let err = "not a feed".parse::<rss::Channel>().unwrap_err();
+        assert_eq!(Error::from(err).class(), ErrorClass::Feed);
+        assert_eq!(Error::FeedStatus(feed_url(), 404).class(), ErrorClass::Feed);
+        assert_eq!(
+            Error::TooManyRedirects(feed_url()).class(),
+            ErrorClass::Feed
+        );
+    }
+
+    #[test]
+    fn transient_errors() {
+        let err = std::io::Error::other("disk full");
+        assert_eq!(Error::from(err).class(), ErrorClass::Transient);
+        assert_eq!(instance_error(429).class(), ErrorClass::Transient);
+        assert_eq!(instance_error(502).class(), ErrorClass::Transient);
+    }
+
+    #[test]
+    fn fatal_errors() {
+        let Error::Megalodon(err) = instance_error(401) else {
+            unreachable!()
+        };
+        assert!(Error::Unauthorized("default".to_owned(), err).is_fatal());
+        assert!(Error::NoAccounts.is_fatal());
+        assert!(Error::NoAccessToken("default".to_owned()).is_fatal());
+        assert!(Error::UnknownAccount("news".to_owned(), feed_url()).is_fatal());
+        assert!(!Error::NoTitle(feed_url()).is_fatal());
+    }
+}
@@ -0,0 +1,83 @@
+use std::io::Write;
+
+use megalodon::megalodon::AppInputOptions;
+
+use crate::{
+    cli::LoginArgs,
+    config::{Account, ConfigFile, Platform},
+    errors::{Error as PError, Result as PResult},
+};
+
+/// The name of the registered application.
+const APP_NAME: &str = "pleroma-rss";
+/// The scopes that the bot needs.
+const SCOPES: [&str; 3] = ["write:statuses", "write:media", "read:accounts"];
+/// The scopes that the bot needs to read its mentions, for `--allow` and `--subscriptions-file`.
+const COMMANDS_SCOPES: [&str; 2] = ["read:notifications", "write:notifications"];
+/// The out-of-band redirect uri, the instance will show the code to the user.
+const NO_REDIRECT: &str = "urn:ietf:wg:oauth:2.0:oob";
+
+/// Registers the bot app in the instance, and exchanges the authorization code
+/// with an access token. The access token will be written to the config file.
+pub async fn run(args: LoginArgs) -> PResult<()> {
+    let platform = if args.is_mastodon {
+        Platform::Mastodon
+    } else {
+        Platform::Pleroma
+    };
+    let base_url = args.instance.as_str().trim_end_matches('/').to_owned();
+    let client = megalodon::generator(platform.sns(), base_url, None, None);
+    let mut scopes = SCOPES.to_vec();
+    if args.commands {
+        scopes.extend(COMMANDS_SCOPES);
+    }
+    tracing::info!("Registering the app in: {}", args.instance);
+    let app = client
+        .register_app(
+            APP_NAME.to_owned(),
+            &AppInputOptions {
+                scopes: Some(scopes.iter().map(ToString::to_string).collect()),
+                redirect_uris: Some(NO_REDIRECT.to_owned()),
+                website: Some(env!("CARGO_PKG_REPOSITORY").to_owned()),
+            },
+        )
+        .await?;
+    let url = app.url.ok_or(PError::NoAuthorizationUrl)?;
+    println!("Open the following URL in your browser and authorize the bot:\n{url}\n");
+    print!("Enter the authorization code: ");
+    std::io::stdout().flush()?;
+    let mut code = String::new();
+    std::io::stdin().read_line(&mut code)?;
+    tracing::info!("Fetching the access token.");
+    let token = client
+        .fetch_access_token(
+            app.client_id,
+            app.client_secret,
+            code.trim().to_owned(),
+            NO_REDIRECT.to_owned(),
+        )
+        .await?;
+    let account = Account {
+        name: args.name,
+        base_url: args.instance,
+        access_token: token.access_token,
+        access_token_file: None,
+        platform,
+        items_sleep: None,
+    };
+    let user = account.client().verify_account_credentials().await?.json();
+    println!("Logged in as: @{}", user.acct);
+
+    let mut config = if args.config.exists() {
+        ConfigFile::read(&args.config)?
+    } else {
+        ConfigFile::default()
+    };
+    config.set_account(account);
+    config.write(&args.config)?;
+    println!(
+        "The access token has been written to: {}",
+        args.config.display()
+    );
+    Ok(())
+}
@@ -1,19 +1,55 @@
-use clap::Parser;
-
 mod bot;
 mod cli;
+mod commands;
 mod config;
 mod errors;
+mod login;
+mod metrics;
+mod opml;
+mod state;
+mod subscriptions;
 mod utils;
 
+use tracing_subscriber::EnvFilter;
+
+/// Initializes the logger, the level is set by the `RUST_LOG` environment variable.
+/// The `log` records of the dependencies are forwarded to the logger.
+fn init_logger(format: cli::LogFormat) {
+    // The logs are written to stderr, so they are not mixed with the output of the subcommands.
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr);
+    match format {
+        cli::LogFormat::Pretty => builder.init(),
+        cli::LogFormat::Json => builder.json().flatten_event(true).init(),
+    }
+}
+
 async fn try_main() -> errors::Result<()> {
-    // Skip the first argument, which is the program name.
-    pretty_env_logger::init();
-    let cli = cli::Cli::parse();
-    cli.check()?;
-    log::debug!("CLI arguments: {:#?}", cli);
-    println!("Running the bot. Press Ctrl+C to stop.");
-    bot::run(cli).await?;
+    let cli = cli::Cli::parse_args();
+    init_logger(cli.log_format);
+    tracing::debug!("CLI arguments: {:#?}", cli);
+    match cli.command {
+        cli::Command::Run(args) => {
+            args.check()?;
+            println!("Running the bot. Press Ctrl+C to stop.");
+            bot::run(args).await?;
+        }
+        cli::Command::Once(args) => {
+            args.check()?;
+            // Without a state, the feeds are seen for the first time on every run.
+            if args.state_file.is_none() && !args.dry_run {
+                return Err(errors::Error::NoStateFile);
+            }
+            bot::once(args).await?;
+        }
+        cli::Command::Check(args) => commands::check(args).await?,
+        cli::Command::CheckFeeds(args) => commands::check_feeds(args).await?,
+        cli::Command::List(args) => commands::list(args)?,
+        cli::Command::Import(args) => commands::import(args)?,
+        cli::Command::Export(args) => commands::export(args)?,
+        cli::Command::Login(args) => login::run(args).await?,
+    }
     Ok(())
 }
 
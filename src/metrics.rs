@@ -0,0 +1,101 @@
+use once_cell::sync::Lazy;
+#[cfg(feature = "preview-image")]
+use prometheus::{register_histogram, register_int_counter, Histogram, IntCounter};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+
+/// The feed fetches by feed and result (`ok` or `error`).
+pub static FEED_FETCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pleroma_rss_feed_fetches_total",
+        "The number of feed fetches.",
+        &["feed", "result"]
+    )
+    .expect("The metric is valid")
+});
+
+/// The feed fetch durations by feed.
+pub static FEED_FETCH_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "pleroma_rss_feed_fetch_duration_seconds",
+        "The duration of downloading the feed.",
+        &["feed"]
+    )
+    .expect("The metric is valid")
+});
+
+/// The feed HTTP responses by feed and status class (e.g. `2xx`).
+pub static FEED_HTTP_RESPONSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pleroma_rss_feed_http_responses_total",
+        "The number of feed HTTP responses.",
+        &["feed", "class"]
+    )
+    .expect("The metric is valid")
+});
+
+/// The feed errors by feed and error kind.
+pub static FEED_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pleroma_rss_feed_errors_total",
+        "The number of feed errors.",
+        &["feed", "kind"]
+    )
+    .expect("The metric is valid")
+});
+
+/// The feed items by feed and stage (`seen`, `filtered`, `posted` or `failed`).
+pub static ITEMS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pleroma_rss_items_total",
+        "The number of feed items.",
+        &["feed", "stage"]
+    )
+    .expect("The metric is valid")
+});
+
+/// The media upload durations.
+#[cfg(feature = "preview-image")]
+pub static MEDIA_UPLOAD_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "pleroma_rss_media_upload_duration_seconds",
+        "The duration of uploading a media, including the processing wait."
+    )
+    .expect("The metric is valid")
+});
+
+/// The media uploads that took too long to be processed.
+#[cfg(feature = "preview-image")]
+pub static MEDIA_TIMEOUTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pleroma_rss_media_timeouts_total",
+        "The number of media uploads that timed out."
+    )
+    .expect("The metric is valid")
+});
+
+/// The posting queue depth by account.
+pub static QUEUE_DEPTH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "pleroma_rss_queue_depth",
+        "The number of items waiting in the posting queue.",
+        &["account"]
+    )
+    .expect("The metric is valid")
+});
+
+/// Returns the status class of the HTTP status, e.g. `2xx`.
+pub fn status_class(status: reqwest::StatusCode) -> String {
+    format!("{}xx", status.as_u16() / 100)
+}
+
+/// Returns the metrics in the Prometheus text format.
+pub fn gather() -> String {
+    let mut buffer = Vec::new();
+    if let Err(err) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        tracing::error!("Error encoding the metrics: {}", err);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
@@ -0,0 +1,174 @@
+use quick_xml::{
+    escape::escape,
+    events::{BytesStart, Event},
+    Reader,
+};
+use url::Url;
+
+use crate::{bot::Feed, errors::Result as PResult, utils};
+
+/// Returns `true` if the feeds file is an OPML file.
+/// The file is an OPML file if its extension is `opml` or it starts with an XML tag.
+pub fn is_opml(path: &std::path::Path, content: &str) -> bool {
+    path.extension().is_some_and(|ext| ext == "opml") || content.trim_start().starts_with('<')
+}
+
+/// Parses an OPML file. It returns the feeds of the outlines that have a `xmlUrl`.
+/// The titles of the parent outlines, the `category` and the `tags` attributes are the
+/// feed tags, the `accounts` attribute is the feed accounts, and the `fullText` attribute
+/// is the maximum size of the full text of the items. The `tags` attribute is encoded like
+/// the `tags` option of the feeds file, unlike the `category` paths its tags can have a `/`.
+/// ### File format
+/// ```xml
+/// <opml version="2.0">
+///   <body>
+///     <outline text="Tech">
+///       <outline text="Example" xmlUrl="https://example.com/feed" category="news" accounts="tech"/>
+///     </outline>
+///   </body>
+/// </opml>
+/// ```
+pub fn parse(content: &str, only_new: bool) -> PResult<Vec<Feed>> {
+    tracing::debug!("Parsing OPML feeds file...");
+    let mut reader = Reader::from_str(content);
+    reader.trim_text(true);
+    let mut feeds = Vec::new();
+    // The titles of the parent outlines, `None` for the feed outlines.
+    let mut parents: Vec<Option<String>> = Vec::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(outline) if outline.local_name().as_ref() == b"outline" => {
+                let attrs = Attributes::new(&outline, &reader)?;
+                if attrs.xml_url.is_some() {
+                    feeds.push(attrs.into_feed(&parents, only_new)?);
+                    parents.push(None);
+                } else {
+                    parents.push(attrs.title);
+                }
+            }
+            Event::Empty(outline) if outline.local_name().as_ref() == b"outline" => {
+                let attrs = Attributes::new(&outline, &reader)?;
+                if attrs.xml_url.is_some() {
+                    feeds.push(attrs.into_feed(&parents, only_new)?);
+                }
+            }
+            Event::End(outline) if outline.local_name().as_ref() == b"outline" => {
+                parents.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(feeds)
+}
+
+/// Writes the feeds as an OPML file. The feed options are written as custom attributes.
+/// The tags are written to the `tags` attribute, and the tags without a `/` or a `,`
+/// to the `category` attribute too, for the other readers.
+pub fn write(feeds: &[Feed]) -> String {
+    let mut opml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<opml version=\"2.0\">\n",
+        "  <head>\n",
+        "    <title>pleroma-rss feeds</title>\n",
+        "  </head>\n",
+        "  <body>\n"
+    ));
+    for feed in feeds {
+        let title = feed.title.as_deref().unwrap_or(feed.url.as_str());
+        opml.push_str(&format!(
+            "    <outline type=\"rss\" text=\"{title}\" title=\"{title}\" xmlUrl=\"{}\"",
+            escape(feed.url.as_str()),
+            title = escape(title)
+        ));
+        let categories = feed
+            .tags
+            .iter()
+            .filter(|tag| !tag.contains(['/', ',']))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !categories.is_empty() {
+            opml.push_str(&format!(" category=\"{}\"", escape(&categories.join(","))));
+        }
+        if !feed.tags.is_empty() {
+            opml.push_str(&format!(
+                " tags=\"{}\"",
+                escape(&utils::encode_list(&feed.tags))
+            ));
+        }
+        if !feed.accounts.is_empty() {
+            opml.push_str(&format!(
+                " accounts=\"{}\"",
+                escape(&feed.accounts.join(","))
+            ));
+        }
+        if let Some(max_size) = feed.full_text {
+            opml.push_str(&format!(" fullText=\"{max_size}\""));
+        }
+        opml.push_str("/>\n");
+    }
+    opml.push_str("  </body>\n</opml>\n");
+    opml
+}
+
+/// The attributes of an outline.
+#[derive(Debug, Default)]
+struct Attributes {
+    xml_url: Option<String>,
+    title: Option<String>,
+    categories: Vec<String>,
+    tags: Vec<String>,
+    accounts: Vec<String>,
+    full_text: Option<usize>,
+}
+
+impl Attributes {
+    /// Reads the attributes of the outline.
+    fn new(outline: &BytesStart, reader: &Reader<&[u8]>) -> PResult<Self> {
+        let mut attrs = Self::default();
+        let mut text = None;
+        for attr in outline.attributes() {
+            let attr = attr.map_err(quick_xml::Error::from)?;
+            let value = attr.decode_and_unescape_value(reader)?.trim().to_owned();
+            match attr.key.local_name().as_ref() {
+                b"xmlUrl" => attrs.xml_url = Some(value),
+                b"title" => attrs.title = Some(value),
+                b"text" => text = Some(value),
+                b"category" => attrs.categories = split_list(&value, &[',', '/']),
+                b"tags" => attrs.tags = utils::split_list(&value),
+                b"accounts" => attrs.accounts = split_list(&value, &[',']),
+                b"fullText" => attrs.full_text = value.parse().ok(),
+                _ => {}
+            }
+        }
+        attrs.title = attrs.title.or(text).filter(|title| !title.is_empty());
+        Ok(attrs)
+    }
+
+    /// Creates a feed from the outline attributes.
+    fn into_feed(self, parents: &[Option<String>], only_new: bool) -> PResult<Feed> {
+        let url = Url::parse(self.xml_url.as_deref().unwrap_or_default())?;
+        tracing::debug!("Parsing feed: {}, only_new: {}", url, only_new);
+        let mut feed = Feed::new(url, only_new);
+        feed.title = self.title;
+        feed.accounts = self.accounts;
+        feed.full_text = self.full_text;
+        let parents = parents.iter().flatten().cloned();
+        for tag in parents.chain(self.tags).chain(self.categories) {
+            if !feed.tags.contains(&tag) {
+                feed.tags.push(tag);
+            }
+        }
+        Ok(feed)
+    }
+}
+
+/// Splits a list attribute, skipping the empty items.
+fn split_list(value: &str, separators: &[char]) -> Vec<String> {
+    value
+        .split(separators)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
@@ -0,0 +1,99 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{bot::Feed, errors::Result as PResult, utils};
+
+/// The persisted state of a feed.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FeedState {
+    /// The last post date.
+    pub last_post: Option<u64>,
+    /// The number of consecutive failures.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub failures: u32,
+    /// The feed is disabled after too many failures.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// The url that the feed is moved to permanently.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moved_to: Option<Url>,
+}
+
+impl FeedState {
+    /// Returns the state of the feed.
+    pub fn of(feed: &Feed) -> Self {
+        Self {
+            last_post: feed.last_post,
+            failures: feed.failures,
+            disabled: feed.disabled,
+            moved_to: feed.moved_to.clone(),
+        }
+    }
+
+    /// Restores the state of the feed, the last post is kept if the state does not have one.
+    pub fn restore(&self, feed: &mut Feed) {
+        if let Some(last_post) = self.last_post {
+            tracing::debug!("Restoring the last post of {}: {}", feed.url, last_post);
+            feed.last_post = Some(last_post);
+        }
+        feed.failures = self.failures;
+        feed.disabled = self.disabled;
+        feed.moved_to = self.moved_to.clone();
+    }
+}
+
+/// The persisted state of the bot, the feeds state by their url.
+/// This is used to keep the last post of the feeds between the restarts.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct State {
+    /// The feeds state.
+    pub feeds: HashMap<String, FeedState>,
+}
+
+impl State {
+    /// Reads the state file. Returns an empty state if the file does not exist.
+    pub fn read(path: &Path) -> PResult<Self> {
+        if !path.exists() {
+            tracing::debug!("The state file {} does not exist.", path.display());
+            return Ok(Self::default());
+        }
+        tracing::debug!("Reading state file: {}", path.display());
+        serde_json::from_str(&std::fs::read_to_string(path)?).map_err(From::from)
+    }
+
+    /// Writes the state file atomically, so a crash does not leave a partial state.
+    pub fn write(&self, path: &Path) -> PResult<()> {
+        tracing::debug!("Writing state file: {}", path.display());
+        utils::write_atomic(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Creates the state from the feeds.
+    pub fn from_feeds(feeds: &[Feed]) -> Self {
+        Self {
+            feeds: feeds
+                .iter()
+                .map(|feed| (feed.url.to_string(), FeedState::of(feed)))
+                .collect(),
+        }
+    }
+
+    /// Returns the state of the feed.
+    pub fn feed(&self, feed: &Feed) -> Option<&FeedState> {
+        self.feeds.get(feed.url.as_str())
+    }
+
+    /// Applies the state to the feeds, the feeds without a state are not changed.
+    pub fn apply(&self, feeds: &mut [Feed]) {
+        for feed in feeds {
+            if let Some(state) = self.feed(feed) {
+                state.restore(feed);
+            }
+        }
+    }
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
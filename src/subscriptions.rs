@@ -0,0 +1,93 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::Result as PResult, state::FeedState, utils};
+
+/// A user that is subscribed to a feed by sending a direct message to a bot account.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Subscriber {
+    /// The bot account that the user sent the message to, the items are sent from it.
+    pub account: String,
+    /// The user account, e.g. `user@example.com` or `user` for the local accounts.
+    pub acct: String,
+}
+
+/// A feed that the users are subscribed to.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SubscribedFeed {
+    /// The state of the feed, it is checked like the feeds of the feeds file.
+    #[serde(flatten)]
+    pub state: FeedState,
+    /// The users that receive the new items.
+    pub subscribers: Vec<Subscriber>,
+}
+
+/// The subscriptions of the users, the subscribed feeds by their url.
+/// The items of a feed are fetched once and sent to all of its subscribers.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Subscriptions {
+    /// The subscribed feeds.
+    pub feeds: BTreeMap<String, SubscribedFeed>,
+}
+
+impl Subscriptions {
+    /// Reads the subscriptions file. Returns empty subscriptions if the file does not exist.
+    pub fn read(path: &Path) -> PResult<Self> {
+        if !path.exists() {
+            tracing::debug!("The subscriptions file {} does not exist.", path.display());
+            return Ok(Self::default());
+        }
+        tracing::debug!("Reading subscriptions file: {}", path.display());
+        serde_json::from_str(&std::fs::read_to_string(path)?).map_err(From::from)
+    }
+
+    /// Writes the subscriptions file atomically, so a crash does not lose the subscriptions.
+    pub fn write(&self, path: &Path) -> PResult<()> {
+        tracing::debug!("Writing subscriptions file: {}", path.display());
+        utils::write_atomic(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Returns the urls of the feeds that the user is subscribed to.
+    pub fn of(&self, subscriber: &Subscriber) -> Vec<&str> {
+        self.feeds
+            .iter()
+            .filter(|(_, feed)| feed.subscribers.contains(subscriber))
+            .map(|(url, _)| url.as_str())
+            .collect()
+    }
+
+    /// Subscribes the user to the feed, the feed has just been checked.
+    /// A new feed starts from `state`, and a disabled feed is enabled again.
+    pub fn subscribe(&mut self, url: &str, subscriber: Subscriber, state: FeedState) {
+        let feed = self
+            .feeds
+            .entry(url.to_owned())
+            .or_insert_with(|| SubscribedFeed {
+                state,
+                subscribers: Vec::new(),
+            });
+        if feed.state.disabled {
+            feed.state.disabled = false;
+            feed.state.failures = 0;
+        }
+        if !feed.subscribers.contains(&subscriber) {
+            feed.subscribers.push(subscriber);
+        }
+    }
+
+    /// Unsubscribes the user from the feed, the feed is removed without subscribers.
+    /// Returns `false` if the user is not subscribed to the feed.
+    pub fn unsubscribe(&mut self, url: &str, subscriber: &Subscriber) -> bool {
+        let Some(feed) = self.feeds.get_mut(url) else {
+            return false;
+        };
+        let count = feed.subscribers.len();
+        feed.subscribers.retain(|old| old != subscriber);
+        let is_removed = feed.subscribers.len() != count;
+        if feed.subscribers.is_empty() {
+            self.feeds.remove(url);
+        }
+        is_removed
+    }
+}
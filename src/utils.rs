@@ -1,14 +1,15 @@
-use std::io::BufRead;
+use std::io::Write;
 
 use reqwest::Url;
 
 use crate::bot::Feed;
-use crate::errors::Result as PResult;
+use crate::errors::{Error as PError, Result as PResult};
+use crate::opml;
 
 /// Rmove html tags from a string
 /// useing regex
 pub fn remove_html_tags(text: &str) -> String {
-    log::info!(
+    tracing::trace!(
         "Removing html tags from: {}...",
         text.chars().take(10).collect::<String>()
     );
@@ -21,29 +22,141 @@ pub fn remove_html_tags(text: &str) -> String {
 }
 
 /// Parses the RSS feeds file. It returns a list of feeds.
+/// The file can be an OPML file, see [`opml::parse`].
 /// ### File format
 /// The file must contain one feed url per line.
-/// The url must be valid.
+/// The url must be valid, and can be followed by the feed options.
 /// ```text
 /// https://example.com/feed
-/// https://example.com/feed2
+/// https://example.com/feed2 accounts=tech,news tags=linux
+/// https://example.com/feed3 full_text=1000
 /// ```
+/// ### Feed options
+/// - `accounts`: The accounts that the feed items will be posted to, separated by commas.
+///   Without it, the feed items will be posted to all the accounts.
+/// - `tags`: The feed tags, separated by commas.
+/// - `full_text`: The maximum size of the full text of the items in bytes.
+///   With it, the description of the items is extracted from their article page.
+///
+/// The whitespace, commas and `%` in the accounts and tags are percent-encoded,
+/// e.g. `tags=Tech%20News`, see [`encode_list`].
 pub fn parse_feeds(rss_feeds_file: &std::path::Path, only_new: bool) -> PResult<Vec<Feed>> {
-    log::debug!("Opening feeds file...");
-    let file = std::fs::File::open(rss_feeds_file)?;
-    let reader = std::io::BufReader::new(file);
-    log::debug!("Reading feeds file...");
-    reader
+    tracing::debug!("Reading feeds file...");
+    let content = std::fs::read_to_string(rss_feeds_file)?;
+    if opml::is_opml(rss_feeds_file, &content) {
+        return opml::parse(&content, only_new);
+    }
+    content
         .lines()
-        .filter(|line| !line.as_ref().unwrap().is_empty())
-        .map(|line| {
-            let line = line?;
-            log::debug!("Parsing feed: {}, only_new: {}", line, only_new);
-            Ok(Feed::new(Url::parse(&line)?, only_new))
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse_feed(line, only_new))
+        .collect()
+}
+
+/// Returns `true` if the feeds file is an existing OPML file, it is not modified by the bot.
+/// Rewriting it would lose its outlines, e.g. the nested categories and the other attributes.
+pub fn is_opml_file(rss_feeds_file: &std::path::Path) -> bool {
+    std::fs::read_to_string(rss_feeds_file)
+        .is_ok_and(|content| opml::is_opml(rss_feeds_file, &content))
+}
+
+/// Writes the feeds to the feeds file atomically. A new feeds file with the `opml` extension
+/// is written as an OPML file, an existing OPML file is refused, see [`is_opml_file`].
+pub fn write_feeds(rss_feeds_file: &std::path::Path, feeds: &[Feed]) -> PResult<()> {
+    tracing::debug!("Writing feeds file...");
+    if is_opml_file(rss_feeds_file) {
+        return Err(PError::OpmlFeedsFile(rss_feeds_file.display().to_string()));
+    }
+    // The file does not exist when importing to a new feeds file.
+    let content = if opml::is_opml(rss_feeds_file, "") {
+        opml::write(feeds)
+    } else {
+        feeds.iter().map(|feed| format!("{feed}\n")).collect()
+    };
+    write_atomic(rss_feeds_file, content)
+}
+
+/// Writes the file atomically, the contents are written to a temporary file next to it
+/// that replaces the file, so the file is never partially written.
+/// The file is created with the `0600` mode, only the bot user can read it.
+pub fn write_atomic(path: &std::path::Path, contents: impl AsRef<[u8]>) -> PResult<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{file_name}.tmp"));
+    match std::fs::remove_file(&temp) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&temp)?;
+    file.write_all(contents.as_ref())?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path).map_err(From::from)
+}
+
+/// Parses a feed line, the feed url followed by the feed options.
+pub fn parse_feed(line: &str, only_new: bool) -> PResult<Feed> {
+    tracing::debug!("Parsing feed: {}, only_new: {}", line, only_new);
+    let mut parts = line.split_whitespace();
+    let mut feed = Feed::new(Url::parse(parts.next().unwrap_or_default())?, only_new);
+    for option in parts {
+        match option.split_once('=') {
+            Some(("accounts", accounts)) => feed.accounts = split_list(accounts),
+            Some(("tags", tags)) => feed.tags = split_list(tags),
+            Some(("full_text", max_size)) if max_size.parse::<usize>().is_ok() => {
+                feed.full_text = max_size.parse().ok();
+            }
+            _ => {
+                return Err(PError::InvalidFeedOption(
+                    option.to_owned(),
+                    feed.url.clone(),
+                ))
+            }
+        }
+    }
+    Ok(feed)
+}
+
+/// Splits a comma separated list, skipping the empty items.
+/// The items are percent-decoded, an item that is not valid UTF-8 once decoded is kept as is.
+pub fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            urlencoding::decode(item)
+                .map(|item| item.into_owned())
+                .unwrap_or_else(|_| item.to_owned())
         })
         .collect()
 }
 
+/// Joins the items of a feed option as a comma separated list.
+/// The characters that [`parse_feed`] splits on, the whitespace and the commas,
+/// and the `%` are percent-encoded.
+pub fn encode_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| {
+            item.chars()
+                .map(|c| match c {
+                    '%' | ',' => format!("%{:02X}", c as u32),
+                    c if c.is_whitespace() => {
+                        let mut bytes = [0; 4];
+                        c.encode_utf8(&mut bytes)
+                            .bytes()
+                            .map(|byte| format!("%{byte:02X}"))
+                            .collect()
+                    }
+                    c => c.to_string(),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 /// File checkings
 /// Will check
 /// - if the file exists
@@ -51,35 +164,89 @@ pub fn parse_feeds(rss_feeds_file: &std::path::Path, only_new: bool) -> PResult<
 /// - if the file is readable
 /// - if the file is empty
 pub fn check_file(file_name: &str, file: &std::path::Path) -> PResult<()> {
-    log::debug!("Checking file: {}", file.display());
+    tracing::debug!("Checking file: {}", file.display());
     if !file.exists() {
-        log::error!("File: {} does not exist.", file.display());
+        tracing::error!("File: {} does not exist.", file.display());
         return Err(crate::errors::Error::NotFound(
             file_name.to_owned(),
             file.display().to_string(),
         ));
     }
     if !file.is_file() {
-        log::error!("File: {} is not a file.", file.display());
+        tracing::error!("File: {} is not a file.", file.display());
         return Err(crate::errors::Error::NotAFile(
             file_name.to_owned(),
             file.display().to_string(),
         ));
     }
     if std::fs::File::open(file).is_err() {
-        log::error!("File: {} is not readable.", file.display());
+        tracing::error!("File: {} is not readable.", file.display());
         return Err(crate::errors::Error::NotReadable(
             file_name.to_owned(),
             file.display().to_string(),
         ));
     }
     if file.metadata()?.len() == 0 {
-        log::error!("File: {} is empty.", file.display());
+        tracing::error!("File: {} is empty.", file.display());
         return Err(crate::errors::Error::EmptyFile(
             file_name.to_owned(),
             file.display().to_string(),
         ));
     }
-    log::debug!("File: {} is ok.", file.display());
+    tracing::debug!("File: {} is ok.", file.display());
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opml_feeds_to_text() {
+        let content = r#"<opml version="2.0">
+  <body>
+    <outline text="Tech News">
+      <outline text="Example" xmlUrl="https://example.com/feed" category="100% Linux,Open source" accounts="tech"/>
+    </outline>
+    <outline text="Other" xmlUrl="https://example.com/feed2" fullText="1000"/>
+  </body>
+</opml>"#;
+        let feeds = opml::parse(content, false).unwrap();
+        assert_eq!(feeds[0].tags, ["Tech News", "100% Linux", "Open source"]);
+        let text: String = feeds.iter().map(|feed| format!("{feed}\n")).collect();
+        assert_eq!(
+            text,
+            "https://example.com/feed accounts=tech tags=Tech%20News,100%25%20Linux,Open%20source\n\
+             https://example.com/feed2 full_text=1000\n"
+        );
+        let parsed = text
+            .lines()
+            .map(|line| parse_feed(line, false))
+            .collect::<PResult<Vec<_>>>()
+            .unwrap();
+        for (feed, parsed) in feeds.iter().zip(&parsed) {
+            assert_eq!(parsed.url, feed.url);
+            assert_eq!(parsed.accounts, feed.accounts);
+            assert_eq!(parsed.tags, feed.tags);
+            assert_eq!(parsed.full_text, feed.full_text);
+        }
+    }
+
+    #[test]
+    fn opml_tags_round_trip() {
+        let mut feed = parse_feed("https://example.com/feed", false).unwrap();
+        feed.tags = vec!["TV/Film".to_owned(), "a,b".to_owned(), "news".to_owned()];
+        let content = opml::write(std::slice::from_ref(&feed));
+        assert!(content.contains(r#"category="news""#));
+        assert_eq!(opml::parse(&content, false).unwrap()[0].tags, feed.tags);
+    }
+
+    #[test]
+    fn encoded_lists() {
+        let tags = vec!["a,b".to_owned(), "tab\there".to_owned()];
+        assert_eq!(encode_list(&tags), "a%2Cb,tab%09here");
+        assert_eq!(split_list(&encode_list(&tags)), tags);
+        // The existing files may have a `%` that is not an escape.
+        assert_eq!(split_list("100%,news"), ["100%", "news"]);
+    }
+}